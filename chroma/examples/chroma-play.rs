@@ -1,42 +1,46 @@
-use chroma::{Key, KeyboardCustomKeyEffectBuilder, KeyboardStaticEffectBuilder};
+use chroma::{
+    Key, KeyboardCustomKeyEffectBuilder, KeyboardStaticEffectBuilder, LightingBackend,
+    NativeBackend,
+};
 use rgb::RGB8;
-use std::{error::Error, thread::sleep, time::Duration};
+use std::{error::Error, sync::Arc, thread::sleep, time::Duration};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let example = std::env::args()
-        .skip(1)
-        .next()
+        .nth(1)
         .unwrap_or("static-cycle".to_string());
 
+    let backend: Arc<dyn LightingBackend> = Arc::new(NativeBackend::load()?);
+
     match example.as_str() {
-        "static-cycle" => static_cycle(),
-        "grid" => grid(),
-        "numpad" => numpad(),
+        "static-cycle" => static_cycle(backend),
+        "grid" => grid(backend),
+        "numpad" => numpad(backend),
         _ => panic!("Unrecognized example type"),
     }
 }
 
-fn static_cycle() -> Result<(), Box<dyn Error>> {
+fn static_cycle(backend: Arc<dyn LightingBackend>) -> Result<(), Box<dyn Error>> {
     let red = KeyboardStaticEffectBuilder::new(RGB8 {
         r: 0xff,
         g: 0x00,
         b: 0x00,
     })
-    .build()?;
+    .build(backend.clone())?;
 
     let green = KeyboardStaticEffectBuilder::new(RGB8 {
         r: 0x00,
         g: 0xff,
         b: 0x00,
     })
-    .build()?;
+    .build(backend.clone())?;
 
     let blue = KeyboardStaticEffectBuilder::new(RGB8 {
         r: 0x00,
         g: 0x00,
         b: 0xff,
     })
-    .build()?;
+    .build(backend.clone())?;
 
     for effect in [red, green, blue].iter().cycle() {
         effect.set()?;
@@ -47,7 +51,7 @@ fn static_cycle() -> Result<(), Box<dyn Error>> {
     unreachable!()
 }
 
-fn grid() -> Result<(), Box<dyn Error>> {
+fn grid(backend: Arc<dyn LightingBackend>) -> Result<(), Box<dyn Error>> {
     loop {
         for row in 0..chroma::MAX_ROW {
             for column in 0..chroma::MAX_COLUMN {
@@ -61,7 +65,7 @@ fn grid() -> Result<(), Box<dyn Error>> {
                             b: 0xff,
                         },
                     )
-                    .build()?;
+                    .build(backend.clone())?;
 
                 effect.set()?;
 
@@ -71,7 +75,7 @@ fn grid() -> Result<(), Box<dyn Error>> {
     }
 }
 
-fn numpad() -> Result<(), Box<dyn Error>> {
+fn numpad(backend: Arc<dyn LightingBackend>) -> Result<(), Box<dyn Error>> {
     let nums = [
         Key::Row1,
         Key::Row2,
@@ -96,7 +100,7 @@ fn numpad() -> Result<(), Box<dyn Error>> {
                         b: 0x00,
                     },
                 )
-                .build()
+                .build(backend.clone())
         })
         .collect::<Result<Vec<_>, _>>()?;
 
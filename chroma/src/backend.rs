@@ -0,0 +1,23 @@
+use crate::{sys, Result};
+
+mod native;
+mod virtual_keyboard;
+
+pub use native::*;
+pub use virtual_keyboard::*;
+
+/// A device (real or otherwise) that effects can be created on and applied to.
+pub trait LightingBackend: Send + Sync {
+    fn create_keyboard_effect(&self, effect: &KeyboardEffect) -> Result<sys::RZEFFECTID>;
+
+    fn set_effect(&self, id: sys::RZEFFECTID) -> Result<()>;
+
+    fn delete_effect(&self, id: sys::RZEFFECTID) -> Result<()>;
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Copy, Clone, Debug)]
+pub enum KeyboardEffect {
+    Static(sys::keyboard::STATIC_EFFECT_TYPE),
+    CustomKey(sys::keyboard::CUSTOM_KEY_EFFECT_TYPE),
+}
@@ -0,0 +1,180 @@
+use std::{
+    mem::{self, MaybeUninit},
+    path::PathBuf,
+    ptr,
+    sync::RwLock,
+};
+
+use libloading::Symbol;
+
+use crate::{
+    backend::{KeyboardEffect, LightingBackend},
+    sys, ChromaError, Result,
+};
+
+/// Talks to a real device through the Razer Chroma SDK.
+#[derive(Copy, Clone, Debug)]
+pub struct NativeBackend {
+    _private: (),
+}
+
+impl NativeBackend {
+    /// Loads and initializes the Razer Chroma SDK, if it hasn't been already.
+    pub fn load() -> Result<Self> {
+        unsafe {
+            lib()?;
+        }
+
+        Ok(Self { _private: () })
+    }
+}
+
+impl LightingBackend for NativeBackend {
+    fn create_keyboard_effect(&self, effect: &KeyboardEffect) -> Result<sys::RZEFFECTID> {
+        let mut effect = *effect;
+        let (effect_type, param): (_, sys::PRZPARAM) = match &mut effect {
+            KeyboardEffect::Static(effect_type) => (
+                sys::KEYBOARD_EFFECT_TYPE::CHROMA_STATIC,
+                effect_type as *mut _ as *mut _,
+            ),
+            KeyboardEffect::CustomKey(effect_type) => (
+                sys::KEYBOARD_EFFECT_TYPE::CHROMA_CUSTOM_KEY,
+                effect_type as *mut _ as *mut _,
+            ),
+        };
+
+        unsafe {
+            let mut effect_id = MaybeUninit::uninit();
+
+            (*lib()?.create_keyboard_effect_fn)(effect_type, param, effect_id.as_mut_ptr()).r()?;
+
+            Ok(effect_id.assume_init())
+        }
+    }
+
+    fn set_effect(&self, id: sys::RZEFFECTID) -> Result<()> {
+        unsafe {
+            (*lib()?.set_effect_fn)(id).r()?;
+        }
+
+        Ok(())
+    }
+
+    fn delete_effect(&self, id: sys::RZEFFECTID) -> Result<()> {
+        unsafe {
+            (*lib()?.delete_effect_fn)(id).r()?;
+        }
+
+        Ok(())
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref CHROMA_LIBRARY: RwLock<Option<ChromaLibrary>> = RwLock::default();
+}
+
+unsafe fn lib() -> Result<&'static ChromaLibrary> {
+    match CHROMA_LIBRARY.try_read() {
+        Ok(guard) => {
+            if let Some(lib) = guard.as_ref() {
+                // It's safe to erase the lifetime because once the Library is loaded, we never mutate
+                // it again.
+                return Ok(&*(lib as *const _));
+            }
+        }
+        Err(std::sync::TryLockError::Poisoned(_)) => panic!(),
+        _ => {}
+    }
+
+    // Initialize CHROMA_LIBRARY
+    {
+        let mut lib = CHROMA_LIBRARY.write().unwrap();
+        if lib.is_none() {
+            *lib = Some(ChromaLibrary::load()?);
+        }
+    }
+
+    // It's safe to erase the lifetime because once the Library is loaded, we never mutate it again.
+    let lib = CHROMA_LIBRARY.read().unwrap();
+    let lib = lib.as_ref().unwrap();
+    Ok(&*(lib as *const _))
+}
+
+#[allow(dead_code)]
+struct ChromaLibrary {
+    sdk: *const libloading::Library,
+    uninit_fn: Symbol<'static, sys::UnInitFn>,
+    create_effect_fn: Symbol<'static, sys::CreateEffectFn>,
+    create_keyboard_effect_fn: Symbol<'static, sys::CreateKeyboardEffectFn>,
+    create_mouse_effect_fn: Symbol<'static, sys::CreateMouseEffectFn>,
+    create_headset_effect_fn: Symbol<'static, sys::CreateHeadsetEffectFn>,
+    create_mousepad_effect_fn: Symbol<'static, sys::CreateMousepadEffectFn>,
+    create_keypad_effect_fn: Symbol<'static, sys::CreateKeypadEffectFn>,
+    create_chroma_link_effect_fn: Symbol<'static, sys::CreateChromaLinkEffectFn>,
+    delete_effect_fn: Symbol<'static, sys::DeleteEffectFn>,
+    set_effect_fn: Symbol<'static, sys::SetEffectFn>,
+    register_event_notification_fn: Symbol<'static, sys::RegisterEventNotificationFn>,
+    unregister_event_notification_fn: Symbol<'static, sys::UnregisterEventNotificationFn>,
+    query_device_fn: Symbol<'static, sys::QueryDeviceFn>,
+}
+
+unsafe impl Send for ChromaLibrary {}
+unsafe impl Sync for ChromaLibrary {}
+
+impl ChromaLibrary {
+    fn load() -> Result<Self> {
+        let program_files = std::env::var_os("ProgramFiles").ok_or(ChromaError::SdkNotFound)?;
+        let sdk_path = PathBuf::from(program_files).join("Razer Chroma SDK/bin/RzChromaSDK64.dll");
+
+        let sdk = Box::leak(Box::new(libloading::Library::new(sdk_path)?));
+
+        let init_fn: Symbol<sys::InitFn> = unsafe { sdk.get(b"Init\0")? };
+
+        unsafe {
+            init_fn().r()?;
+        }
+
+        let uninit_fn = unsafe { sdk.get(b"UnInit\0")? };
+        let create_effect_fn = unsafe { sdk.get(b"CreateEffect\0")? };
+        let create_keyboard_effect_fn = unsafe { sdk.get(b"CreateKeyboardEffect\0")? };
+        let create_mouse_effect_fn = unsafe { sdk.get(b"CreateMouseEffect\0")? };
+        let create_headset_effect_fn = unsafe { sdk.get(b"CreateHeadsetEffect\0")? };
+        let create_mousepad_effect_fn = unsafe { sdk.get(b"CreateMousepadEffect\0")? };
+        let create_keypad_effect_fn = unsafe { sdk.get(b"CreateKeypadEffect\0")? };
+        let create_chroma_link_effect_fn = unsafe { sdk.get(b"CreateChromaLinkEffect\0")? };
+        let delete_effect_fn = unsafe { sdk.get(b"DeleteEffect\0")? };
+        let set_effect_fn = unsafe { sdk.get(b"SetEffect\0")? };
+        let register_event_notification_fn = unsafe { sdk.get(b"RegisterEventNotification\0")? };
+        let unregister_event_notification_fn =
+            unsafe { sdk.get(b"UnregisterEventNotification\0")? };
+        let query_device_fn = unsafe { sdk.get(b"QueryDevice\0")? };
+
+        Ok(Self {
+            sdk: sdk as *const _,
+            uninit_fn,
+            create_effect_fn,
+            create_keyboard_effect_fn,
+            create_mouse_effect_fn,
+            create_headset_effect_fn,
+            create_mousepad_effect_fn,
+            create_keypad_effect_fn,
+            create_chroma_link_effect_fn,
+            delete_effect_fn,
+            set_effect_fn,
+            register_event_notification_fn,
+            unregister_event_notification_fn,
+            query_device_fn,
+        })
+    }
+}
+
+impl Drop for ChromaLibrary {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = (*self.uninit_fn)();
+            drop(Box::from_raw(
+                mem::replace(&mut self.sdk, ptr::null()) as *mut libloading::Library
+            ));
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::Instant,
+};
+
+use rgb::RGB8;
+
+use crate::{
+    backend::{KeyboardEffect, LightingBackend},
+    effect::rgb_from_colorref,
+    sys::{self, GUID, RZEFFECTID},
+    ChromaError, Key, Result,
+};
+
+/// A keyboard that only exists in memory. Instead of lighting up a device, every applied effect is
+/// captured as a `Frame`.
+#[derive(Default)]
+pub struct VirtualKeyboard {
+    state: Mutex<VirtualKeyboardState>,
+}
+
+#[derive(Default)]
+struct VirtualKeyboardState {
+    next_id: u32,
+    effects: HashMap<RZEFFECTID, KeyboardEffect>,
+    frames: VecDeque<Frame>,
+    frame_limit: Option<usize>,
+}
+
+/// The colors shown on the keyboard after an effect was applied.
+#[derive(Copy, Clone, Debug)]
+pub struct Frame {
+    pub timestamp: Instant,
+    pub grid: sys::keyboard::CUSTOM_KEY_EFFECT_TYPE,
+}
+
+impl Frame {
+    fn new(timestamp: Instant, effect: &KeyboardEffect) -> Self {
+        let grid = match effect {
+            KeyboardEffect::Static(effect) => sys::keyboard::CUSTOM_KEY_EFFECT_TYPE {
+                color: [[effect.color; sys::MAX_COLUMN]; sys::MAX_ROW],
+                ..Default::default()
+            },
+            KeyboardEffect::CustomKey(effect) => *effect,
+        };
+
+        Self { timestamp, grid }
+    }
+
+    /// The color shown at a position in the grid, taking key colors into account.
+    pub fn position(&self, row: u8, column: u8) -> RGB8 {
+        let (row, column) = (row as usize, column as usize);
+
+        let key = self.grid.key[row][column];
+        if key & 0x1000000 != 0 {
+            rgb_from_colorref(key & 0xffffff)
+        } else {
+            rgb_from_colorref(self.grid.color[row][column])
+        }
+    }
+
+    /// The color shown on a key.
+    pub fn key(&self, key: Key) -> RGB8 {
+        self.position(key.row(), key.column())
+    }
+}
+
+impl VirtualKeyboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only keep the `limit` most recent frames.
+    pub fn with_frame_limit(limit: usize) -> Self {
+        let keyboard = Self::default();
+        keyboard.state.lock().unwrap().frame_limit = Some(limit);
+        keyboard
+    }
+
+    /// The frame currently shown on the keyboard, if any effect has been applied.
+    pub fn current(&self) -> Option<Frame> {
+        self.state.lock().unwrap().frames.back().copied()
+    }
+
+    /// All captured frames, oldest first.
+    pub fn frames(&self) -> Vec<Frame> {
+        self.state.lock().unwrap().frames.iter().copied().collect()
+    }
+
+    /// Removes and returns all captured frames, oldest first.
+    pub fn take_frames(&self) -> Vec<Frame> {
+        self.state.lock().unwrap().frames.drain(..).collect()
+    }
+}
+
+impl LightingBackend for VirtualKeyboard {
+    fn create_keyboard_effect(&self, effect: &KeyboardEffect) -> Result<RZEFFECTID> {
+        let mut state = self.state.lock().unwrap();
+
+        state.next_id += 1;
+        let id = RZEFFECTID(GUID {
+            Data1: state.next_id,
+            Data2: 0,
+            Data3: 0,
+            Data4: [0; 8],
+        });
+
+        state.effects.insert(id, *effect);
+        Ok(id)
+    }
+
+    fn set_effect(&self, id: RZEFFECTID) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+
+        let effect = state.effects.get(&id).ok_or(ChromaError::RzNotFound)?;
+        let frame = Frame::new(Instant::now(), effect);
+        state.frames.push_back(frame);
+
+        if let Some(limit) = state.frame_limit {
+            while state.frames.len() > limit {
+                state.frames.pop_front();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn delete_effect(&self, id: RZEFFECTID) -> Result<()> {
+        self.state
+            .lock()
+            .unwrap()
+            .effects
+            .remove(&id)
+            .map(|_| ())
+            .ok_or(ChromaError::RzNotFound)
+    }
+}
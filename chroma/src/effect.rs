@@ -1,32 +1,26 @@
-use std::mem::MaybeUninit;
+use std::sync::Arc;
 
 use rgb::RGB8;
-use winapi::shared::windef::COLORREF;
 
-use crate::{lib, sys, Result};
+use crate::{
+    sys::{self, COLORREF},
+    KeyboardEffect, LightingBackend, Result,
+};
 
-pub struct Effect(pub(crate) super::sys::RZEFFECTID);
+pub struct Effect {
+    backend: Arc<dyn LightingBackend>,
+    id: sys::RZEFFECTID,
+}
 
 impl Effect {
     pub fn set(&self) -> Result<()> {
-        unsafe {
-            (*lib()?.set_effect_fn)(self.0).r()?;
-        }
-
-        Ok(())
+        self.backend.set_effect(self.id)
     }
 }
 
 impl Drop for Effect {
     fn drop(&mut self) {
-        unsafe {
-            let lib = if let Ok(lib) = lib() {
-                lib
-            } else {
-                return;
-            };
-            std::mem::forget((*lib.delete_effect_fn)(self.0));
-        }
+        let _ = self.backend.delete_effect(self.id);
     }
 }
 
@@ -40,28 +34,17 @@ impl KeyboardStaticEffectBuilder {
         Self { color }
     }
 
-    pub fn set_color<'a>(&'a mut self, color: RGB8) -> &'a mut Self {
+    pub fn set_color(&mut self, color: RGB8) -> &mut Self {
         self.color = color;
         self
     }
 
-    pub fn build(&self) -> Result<Effect> {
-        let mut effect_type = sys::keyboard::STATIC_EFFECT_TYPE {
+    pub fn build(&self, backend: Arc<dyn LightingBackend>) -> Result<Effect> {
+        let effect_type = sys::keyboard::STATIC_EFFECT_TYPE {
             color: colorref_from_rgb(self.color),
         };
 
-        unsafe {
-            let mut effect_id = MaybeUninit::uninit();
-
-            (*lib()?.create_keyboard_effect_fn)(
-                sys::KEYBOARD_EFFECT_TYPE::CHROMA_STATIC,
-                &mut effect_type as *mut _ as *mut _,
-                effect_id.as_mut_ptr(),
-            )
-            .r()?;
-
-            Ok(Effect(effect_id.assume_init()))
-        }
+        build_keyboard_effect(backend, KeyboardEffect::Static(effect_type))
     }
 }
 
@@ -75,7 +58,7 @@ impl KeyboardCustomKeyEffectBuilder {
         Self::default()
     }
 
-    pub fn clear<'a>(&'a mut self) -> &'a mut Self {
+    pub fn clear(&mut self) -> &mut Self {
         *self = Default::default();
         self
     }
@@ -84,7 +67,7 @@ impl KeyboardCustomKeyEffectBuilder {
         rgb_from_colorref(self.effect_type.color[i as usize][j as usize])
     }
 
-    pub fn set_position<'a>(&'a mut self, row: u8, column: u8, color: RGB8) -> &'a mut Self {
+    pub fn set_position(&mut self, row: u8, column: u8, color: RGB8) -> &mut Self {
         let (row, column) = (row as usize, column as usize);
 
         self.effect_type.color[row][column] = colorref_from_rgb(color);
@@ -99,37 +82,72 @@ impl KeyboardCustomKeyEffectBuilder {
         )
     }
 
-    pub fn set_key<'a>(&'a mut self, key: crate::Key, color: RGB8) -> &'a mut Self {
+    pub fn set_key(&mut self, key: crate::Key, color: RGB8) -> &mut Self {
         let (row, column) = (key.row() as usize, key.column() as usize);
         self.effect_type.key[row][column] = colorref_from_rgb(color) | 0x1000000;
         self
     }
 
-    pub fn build(&self) -> Result<Effect> {
-        let mut effect_type = self.effect_type;
-
-        let mut effect_id = MaybeUninit::uninit();
-        unsafe {
-            (*lib()?.create_keyboard_effect_fn)(
-                sys::KEYBOARD_EFFECT_TYPE::CHROMA_CUSTOM_KEY,
-                &mut effect_type as *mut _ as *mut _,
-                effect_id.as_mut_ptr(),
-            )
-            .r()?;
-
-            Ok(Effect(effect_id.assume_init()))
-        }
+    pub fn build(&self, backend: Arc<dyn LightingBackend>) -> Result<Effect> {
+        build_keyboard_effect(backend, KeyboardEffect::CustomKey(self.effect_type))
     }
 }
 
-fn rgb_from_colorref(color: COLORREF) -> RGB8 {
+fn build_keyboard_effect(
+    backend: Arc<dyn LightingBackend>,
+    effect: KeyboardEffect,
+) -> Result<Effect> {
+    let id = backend.create_keyboard_effect(&effect)?;
+    Ok(Effect { backend, id })
+}
+
+pub(crate) fn rgb_from_colorref(color: COLORREF) -> RGB8 {
     RGB8 {
-        r: ((color & 0xff0000) >> 16) as u8,
+        r: (color & 0xff) as u8,
         g: ((color & 0xff00) >> 8) as u8,
-        b: (color & 0xff) as u8,
+        b: ((color & 0xff0000) >> 16) as u8,
     }
 }
 
-fn colorref_from_rgb(rgb: RGB8) -> COLORREF {
+pub(crate) fn colorref_from_rgb(rgb: RGB8) -> COLORREF {
     (rgb.b as u32) << 16 | (rgb.g as u32) << 8 | rgb.r as u32
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colorrefs_are_0x00bbggrr() {
+        let color = RGB8::new(0x12, 0x34, 0x56);
+
+        assert_eq!(colorref_from_rgb(color), 0x0056_3412);
+        assert_eq!(rgb_from_colorref(0x0056_3412), color);
+    }
+
+    #[test]
+    fn colors_round_trip_through_colorrefs() {
+        for &color in &[
+            RGB8::new(0, 0, 0),
+            RGB8::new(0xff, 0, 0),
+            RGB8::new(0, 0xff, 0),
+            RGB8::new(0, 0, 0xff),
+            RGB8::new(0x12, 0x34, 0x56),
+            RGB8::new(0xff, 0xff, 0xff),
+        ] {
+            assert_eq!(rgb_from_colorref(colorref_from_rgb(color)), color);
+        }
+    }
+
+    #[test]
+    fn builders_return_the_colors_they_were_given() {
+        let color = RGB8::new(0xff, 0x80, 0x00);
+        let mut builder = KeyboardCustomKeyEffectBuilder::new();
+        builder
+            .set_position(2, 3, color)
+            .set_key(crate::Key::Space, color);
+
+        assert_eq!(builder.position(2, 3), color);
+        assert_eq!(builder.key(crate::Key::Space), color);
+    }
+}
@@ -1,7 +1,5 @@
+use crate::sys::{self, LONG, RZRESULT};
 use quick_error::quick_error;
-use winapi::shared::ntdef::LONG;
-
-use crate::sys::{self, RZRESULT};
 
 pub type Result<T> = std::result::Result<T, ChromaError>;
 
@@ -25,6 +23,7 @@ quick_error! {
         RzNoMoreItems {}
         RzFailed {}
         WinError(hr: LONG) {}
+        SdkNotFound {}
         LoadError(err: libloading::Error) {
             from()
             description(err.description())
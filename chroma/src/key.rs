@@ -129,6 +129,271 @@ pub enum Key {
 }
 
 impl Key {
+    /// Every valid key, in declaration order.
+    pub const ALL: [Key; 123] = [
+        Key::Esc,
+        Key::F1,
+        Key::F2,
+        Key::F3,
+        Key::F4,
+        Key::F5,
+        Key::F6,
+        Key::F7,
+        Key::F8,
+        Key::F9,
+        Key::F10,
+        Key::F11,
+        Key::F12,
+        Key::Row1,
+        Key::Row2,
+        Key::Row3,
+        Key::Row4,
+        Key::Row5,
+        Key::Row6,
+        Key::Row7,
+        Key::Row8,
+        Key::Row9,
+        Key::Row0,
+        Key::A,
+        Key::B,
+        Key::C,
+        Key::D,
+        Key::E,
+        Key::F,
+        Key::G,
+        Key::H,
+        Key::I,
+        Key::J,
+        Key::K,
+        Key::L,
+        Key::M,
+        Key::N,
+        Key::O,
+        Key::P,
+        Key::Q,
+        Key::R,
+        Key::S,
+        Key::T,
+        Key::U,
+        Key::V,
+        Key::W,
+        Key::X,
+        Key::Y,
+        Key::Z,
+        Key::Numlock,
+        Key::Numpad0,
+        Key::Numpad1,
+        Key::Numpad2,
+        Key::Numpad3,
+        Key::Numpad4,
+        Key::Numpad5,
+        Key::Numpad6,
+        Key::Numpad7,
+        Key::Numpad8,
+        Key::Numpad9,
+        Key::NumpadDivide,
+        Key::NumpadMultiply,
+        Key::NumpadSubtract,
+        Key::NumpadAdd,
+        Key::NumpadEnter,
+        Key::NumpadDecimal,
+        Key::Printscreen,
+        Key::Scroll,
+        Key::Pause,
+        Key::Insert,
+        Key::Home,
+        Key::Pageup,
+        Key::Delete,
+        Key::End,
+        Key::Pagedown,
+        Key::Up,
+        Key::Left,
+        Key::Down,
+        Key::Right,
+        Key::Tab,
+        Key::Capslock,
+        Key::Backspace,
+        Key::Enter,
+        Key::Lctrl,
+        Key::Lwin,
+        Key::Lalt,
+        Key::Space,
+        Key::Ralt,
+        Key::Fn,
+        Key::Rmenu,
+        Key::Rctrl,
+        Key::Lshift,
+        Key::Rshift,
+        Key::Macro1,
+        Key::Macro2,
+        Key::Macro3,
+        Key::Macro4,
+        Key::Macro5,
+        Key::Oem1,
+        Key::Oem2,
+        Key::Oem3,
+        Key::Oem4,
+        Key::Oem5,
+        Key::Oem6,
+        Key::Oem7,
+        Key::Oem8,
+        Key::Oem9,
+        Key::Oem10,
+        Key::Oem11,
+        Key::Eur1,
+        Key::Eur2,
+        Key::Jpn1,
+        Key::Jpn2,
+        Key::Jpn3,
+        Key::Jpn4,
+        Key::Jpn5,
+        Key::Kor1,
+        Key::Kor2,
+        Key::Kor3,
+        Key::Kor4,
+        Key::Kor5,
+        Key::Kor6,
+        Key::Kor7,
+    ];
+
+    /// The first key in `Key::ALL` that sits at the given grid position.
+    pub fn at(row: u8, column: u8) -> Option<Key> {
+        Key::ALL
+            .iter()
+            .copied()
+            .find(|key| key.row() == row && key.column() == column)
+    }
+
+    /// A short (at most three character) label for the key.
+    pub fn label(self) -> &'static str {
+        match self {
+            Key::Esc => "Esc",
+            Key::F1 => "F1",
+            Key::F2 => "F2",
+            Key::F3 => "F3",
+            Key::F4 => "F4",
+            Key::F5 => "F5",
+            Key::F6 => "F6",
+            Key::F7 => "F7",
+            Key::F8 => "F8",
+            Key::F9 => "F9",
+            Key::F10 => "F10",
+            Key::F11 => "F11",
+            Key::F12 => "F12",
+            Key::Row1 => "1",
+            Key::Row2 => "2",
+            Key::Row3 => "3",
+            Key::Row4 => "4",
+            Key::Row5 => "5",
+            Key::Row6 => "6",
+            Key::Row7 => "7",
+            Key::Row8 => "8",
+            Key::Row9 => "9",
+            Key::Row0 => "0",
+            Key::A => "A",
+            Key::B => "B",
+            Key::C => "C",
+            Key::D => "D",
+            Key::E => "E",
+            Key::F => "F",
+            Key::G => "G",
+            Key::H => "H",
+            Key::I => "I",
+            Key::J => "J",
+            Key::K => "K",
+            Key::L => "L",
+            Key::M => "M",
+            Key::N => "N",
+            Key::O => "O",
+            Key::P => "P",
+            Key::Q => "Q",
+            Key::R => "R",
+            Key::S => "S",
+            Key::T => "T",
+            Key::U => "U",
+            Key::V => "V",
+            Key::W => "W",
+            Key::X => "X",
+            Key::Y => "Y",
+            Key::Z => "Z",
+            Key::Numlock => "Num",
+            Key::Numpad0 => "N0",
+            Key::Numpad1 => "N1",
+            Key::Numpad2 => "N2",
+            Key::Numpad3 => "N3",
+            Key::Numpad4 => "N4",
+            Key::Numpad5 => "N5",
+            Key::Numpad6 => "N6",
+            Key::Numpad7 => "N7",
+            Key::Numpad8 => "N8",
+            Key::Numpad9 => "N9",
+            Key::NumpadDivide => "N/",
+            Key::NumpadMultiply => "N*",
+            Key::NumpadSubtract => "N-",
+            Key::NumpadAdd => "N+",
+            Key::NumpadEnter => "Ent",
+            Key::NumpadDecimal => "N.",
+            Key::Printscreen => "PrS",
+            Key::Scroll => "ScL",
+            Key::Pause => "Pau",
+            Key::Insert => "Ins",
+            Key::Home => "Hom",
+            Key::Pageup => "PgU",
+            Key::Delete => "Del",
+            Key::End => "End",
+            Key::Pagedown => "PgD",
+            Key::Up => "^",
+            Key::Left => "<",
+            Key::Down => "v",
+            Key::Right => ">",
+            Key::Tab => "Tab",
+            Key::Capslock => "Cap",
+            Key::Backspace => "Bks",
+            Key::Enter => "Ent",
+            Key::Lctrl => "Ctl",
+            Key::Lwin => "Win",
+            Key::Lalt => "Alt",
+            Key::Space => "Spc",
+            Key::Ralt => "Alt",
+            Key::Fn => "Fn",
+            Key::Rmenu => "Mnu",
+            Key::Rctrl => "Ctl",
+            Key::Lshift => "Sft",
+            Key::Rshift => "Sft",
+            Key::Macro1 => "M1",
+            Key::Macro2 => "M2",
+            Key::Macro3 => "M3",
+            Key::Macro4 => "M4",
+            Key::Macro5 => "M5",
+            Key::Oem1 => "`",
+            Key::Oem2 => "-",
+            Key::Oem3 => "=",
+            Key::Oem4 => "[",
+            Key::Oem5 => "]",
+            Key::Oem6 => "\\",
+            Key::Oem7 => ";",
+            Key::Oem8 => "'",
+            Key::Oem9 => ",",
+            Key::Oem10 => ".",
+            Key::Oem11 => "/",
+            Key::Eur1 => "E1",
+            Key::Eur2 => "E2",
+            Key::Jpn1 => "J1",
+            Key::Jpn2 => "J2",
+            Key::Jpn3 => "J3",
+            Key::Jpn4 => "J4",
+            Key::Jpn5 => "J5",
+            Key::Kor1 => "K1",
+            Key::Kor2 => "K2",
+            Key::Kor3 => "K3",
+            Key::Kor4 => "K4",
+            Key::Kor5 => "K5",
+            Key::Kor6 => "K6",
+            Key::Kor7 => "K7",
+            Key::Invalid => "",
+        }
+    }
+
     pub fn row(self) -> u8 {
         (self.rzkey().0 >> 8) as u8
     }
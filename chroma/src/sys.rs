@@ -2,12 +2,6 @@
 
 use std::os::raw::c_void;
 
-use winapi::shared::{
-    guiddef::{IsEqualGUID, GUID},
-    ntdef::LONG,
-    windef::HWND,
-};
-
 mod effect;
 mod key;
 
@@ -67,33 +61,34 @@ pub type UnregisterEventNotificationFn = unsafe extern "C" fn() -> RZRESULT;
 pub type QueryDeviceFn =
     unsafe extern "C" fn(id: RZDEVICEID, device_info: Option<&mut DEVICE_TYPE>) -> RZRESULT;
 
+pub type LONG = i32;
+pub type DWORD = u32;
+pub type COLORREF = DWORD;
+pub type HWND = *mut c_void;
+
+#[allow(non_snake_case)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[repr(C)]
+pub struct GUID {
+    pub Data1: u32,
+    pub Data2: u16,
+    pub Data3: u16,
+    pub Data4: [u8; 8],
+}
+
 pub const MAX_ROW: usize = 6;
 pub const MAX_COLUMN: usize = 22;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[repr(transparent)]
 pub struct RZDEVICEID(pub GUID);
 
-impl PartialEq for RZDEVICEID {
-    fn eq(&self, other: &Self) -> bool {
-        IsEqualGUID(&self.0, &other.0)
-    }
-}
-impl Eq for RZDEVICEID {}
-
 pub type PRZPARAM = *mut c_void;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[repr(transparent)]
 pub struct RZEFFECTID(pub GUID);
 
-impl PartialEq for RZEFFECTID {
-    fn eq(&self, other: &Self) -> bool {
-        IsEqualGUID(&self.0, &other.0)
-    }
-}
-impl Eq for RZEFFECTID {}
-
 #[allow(non_camel_case_types)]
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[repr(C)]
@@ -151,9 +151,7 @@ pub enum CHROMA_LINK_EFFECT_TYPE {
 }
 
 pub mod keyboard {
-    use winapi::shared::windef::COLORREF;
-
-    use crate::{MAX_COLUMN, MAX_ROW};
+    use crate::{sys::COLORREF, MAX_COLUMN, MAX_ROW};
 
     #[derive(Copy, Clone, Default, Debug)]
     #[repr(C)]
@@ -1,13 +1,27 @@
 use tokio::prelude::*;
 
-use std::{error::Error, mem::size_of_val, slice};
+use std::{
+    error::Error,
+    future::Future,
+    time::{Instant, SystemTime},
+};
 
 use clap::Arg;
+use forza::{
+    Datagram, Game, Horizon4Datagram, Horizon5Datagram, Motorsport2023Datagram,
+    Motorsport7DashDatagram, Motorsport7Datagram, Motorsport7SledDatagram, RecordingHeader,
+    RecordingWriter, Telemetry,
+};
 use futures_util::pin_mut;
-use tokio::{fs::File, signal::ctrl_c, stream::StreamExt};
+use tokio::{
+    fs::File,
+    signal::ctrl_c,
+    stream::{Stream, StreamExt},
+};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    let games: Vec<_> = Game::ALL.iter().map(|game| game.name()).collect();
     let matches = clap::App::new("forza-recorder")
         .arg(
             Arg::with_name("local_addr")
@@ -15,6 +29,17 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 .long("local-addr")
                 .default_value("0.0.0.0:18733"),
         )
+        .arg(
+            Arg::with_name("game")
+                .short("g")
+                .long("game")
+                .help(
+                    "The game to record, instead of the one that sends the first datagram. Forza \
+                     Horizon 5 can't be told apart from Forza Horizon 4 by its datagrams",
+                )
+                .possible_values(&games)
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("format")
                 .short("f")
@@ -22,6 +47,13 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 .possible_values(&["json", "raw"])
                 .default_value("json"),
         )
+        .arg(
+            Arg::with_name("notes")
+                .short("n")
+                .long("notes")
+                .help("Notes to store in a raw recording")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("separator")
                 .short("s")
@@ -46,39 +78,99 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let mut output = io::BufWriter::new(Box::pin(output));
 
     let server_addr = matches.value_of("local_addr").unwrap();
+    let mut game = matches.value_of("game").map(|game| game.parse().unwrap());
     let format = matches.value_of("format").unwrap();
 
-    let stream = forza::horizon4(server_addr).await?;
+    let stream = forza::listen_raw(server_addr, game).await?;
     pin_mut!(stream);
+    let ctrl_c = ctrl_c();
+    pin_mut!(ctrl_c);
     match format {
         "json" => {
             let separator = matches.value_of("separator").unwrap_or("\n");
 
-            while let Some(datagram) = tokio::select! {
-                datagram = stream.next() => datagram,
-                _ = ctrl_c() => None,
-            } {
-                let datagram = datagram?;
+            while let Some((game, datagram)) =
+                next_datagram(&mut stream, &mut ctrl_c, &mut game).await?
+            {
                 output
-                    .write(serde_json::to_string(&datagram)?.as_bytes())
+                    .write_all(to_json(game, &datagram)?.as_bytes())
                     .await?;
-                output.write(separator.as_bytes()).await?;
+                output.write_all(separator.as_bytes()).await?;
             }
         }
         "raw" => {
-            while let Some(datagram) = tokio::select! {
-                datagram = stream.next() => datagram,
-                _ = ctrl_c() => None,
-            } {
-                let datagram = datagram?;
-                let datagram = unsafe {
-                    slice::from_raw_parts(&datagram as *const _ as *const _, size_of_val(&datagram))
-                };
-                output.write(datagram).await?;
+            // The recording starts with the first datagram, which also tells which game it's of.
+            if let Some((game, datagram)) =
+                next_datagram(&mut stream, &mut ctrl_c, &mut game).await?
+            {
+                let header = RecordingHeader::new(
+                    game,
+                    SystemTime::now(),
+                    matches.value_of("notes").unwrap_or_default().to_owned(),
+                );
+                let start = Instant::now();
+                let mut recording = RecordingWriter::create(&mut output, &header).await?;
+                recording.write_packet(start.elapsed(), &datagram).await?;
+
+                while let Some((_, datagram)) =
+                    next_datagram(&mut stream, &mut ctrl_c, &mut Some(game)).await?
+                {
+                    recording.write_packet(start.elapsed(), &datagram).await?;
+                }
             }
         }
         _ => unreachable!(),
     }
 
+    output.flush().await?;
+
     Ok(())
 }
+
+/// Receives the next datagram of the `game`, or of the game that sends the first datagram if it's
+/// not known yet. Returns `None` once Ctrl-C is pressed.
+async fn next_datagram(
+    stream: &mut (impl Stream<Item = forza::Result<(Telemetry, Vec<u8>)>> + Unpin),
+    ctrl_c: &mut (impl Future + Unpin),
+    game: &mut Option<Game>,
+) -> Result<Option<(Game, Vec<u8>)>, Box<dyn Error>> {
+    while let Some(datagram) = tokio::select! {
+        datagram = stream.next() => datagram,
+        _ = &mut *ctrl_c => None,
+    } {
+        let (telemetry, datagram) = match datagram {
+            Ok(datagram) => datagram,
+            Err(forza::Error::Decode(e)) => {
+                eprintln!("Warning: skipping datagram: {}", e);
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let game = *game.get_or_insert(telemetry.game);
+        if telemetry.game != game {
+            eprintln!(
+                "Warning: skipping datagram from {} while recording {}",
+                telemetry.game, game
+            );
+            continue;
+        }
+        return Ok(Some((game, datagram)));
+    }
+    Ok(None)
+}
+
+/// Converts a datagram to the JSON that replays of the game's recordings read.
+fn to_json(game: Game, bytes: &[u8]) -> Result<String, Box<dyn Error>> {
+    Ok(match game {
+        Game::Horizon4 => serde_json::to_string(&Horizon4Datagram::from_bytes(bytes)?)?,
+        Game::Horizon5 => serde_json::to_string(&Horizon5Datagram::from_bytes(bytes)?)?,
+        Game::Motorsport7 => {
+            serde_json::to_string(&if bytes.len() == Motorsport7SledDatagram::SIZE {
+                Motorsport7Datagram::Sled(Motorsport7SledDatagram::from_bytes(bytes)?)
+            } else {
+                Motorsport7Datagram::Dash(Motorsport7DashDatagram::from_bytes(bytes)?)
+            })?
+        }
+        Game::Motorsport2023 => serde_json::to_string(&Motorsport2023Datagram::from_bytes(bytes)?)?,
+    })
+}
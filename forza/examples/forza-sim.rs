@@ -0,0 +1,409 @@
+use std::{error::Error, f32::consts::PI};
+
+use clap::Arg;
+use forza::{Dash, Datagram, Horizon4Datagram, Quad, Sled, Vector};
+use futures_util::pin_mut;
+use tokio::{
+    net::UdpSocket,
+    signal::ctrl_c,
+    time::{interval, Duration, Instant},
+};
+
+const IDLE_RPM: f32 = 900.0;
+const MAX_RPM: f32 = 8000.0;
+const UPSHIFT_RPM: f32 = 7400.0;
+const DOWNSHIFT_RPM: f32 = 3500.0;
+const GEAR_RATIOS: [f32; 6] = [3.2, 2.2, 1.6, 1.25, 1.0, 0.82];
+/// Engine revolutions per minute for every meter per second, before the gear ratio.
+const RPM_PER_SPEED: f32 = 160.0;
+const WHEEL_RADIUS: f32 = 0.33;
+
+const LAP_SECONDS: f32 = 40.0;
+const RACE_LAPS: u16 = 3;
+/// How long the game sits in the menus between races.
+const MENU_SECONDS: f32 = 5.0;
+const CARS: u8 = 12;
+
+/// A corner starts with braking, followed by a slow exit over the rumble strips.
+struct Corner {
+    at: f32,
+    braking: f32,
+    exit: f32,
+    /// Positive turns right.
+    direction: f32,
+}
+
+const CORNERS: [Corner; 3] = [
+    Corner {
+        at: 12.0,
+        braking: 2.5,
+        exit: 1.5,
+        direction: 1.0,
+    },
+    Corner {
+        at: 26.0,
+        braking: 2.0,
+        exit: 1.0,
+        direction: -1.0,
+    },
+    Corner {
+        at: 35.0,
+        braking: 3.0,
+        exit: 1.5,
+        direction: 1.0,
+    },
+];
+
+enum Phase {
+    Straight,
+    Braking(f32),
+    Exit(f32),
+}
+
+impl Phase {
+    fn at(lap_time: f32) -> Self {
+        for corner in CORNERS.iter() {
+            let since = lap_time - corner.at;
+            if since >= 0.0 && since < corner.braking {
+                return Phase::Braking(corner.direction);
+            } else if since >= corner.braking && since < corner.braking + corner.exit {
+                return Phase::Exit(corner.direction);
+            }
+        }
+        Phase::Straight
+    }
+}
+
+/// A car driving laps around a made-up track, with the race starting over after a short break in
+/// the menus.
+struct Sim {
+    rng: u64,
+    timestamp: f32,
+    race_time: f32,
+    /// Time left in the menus, while the race is off.
+    menu_time: f32,
+    speed: f32,
+    gear: usize,
+    yaw: f32,
+    position: Vector<f32>,
+    distance: f32,
+    fuel: f32,
+    tire_temp: f32,
+    lap: u16,
+    last_lap: f32,
+    best_lap: f32,
+    race_position: u8,
+    next_overtake: f32,
+}
+
+impl Sim {
+    fn new(seed: u64) -> Self {
+        let mut sim = Self {
+            // xorshift gets stuck on zero.
+            rng: seed.max(1),
+            timestamp: 0.0,
+            race_time: 0.0,
+            menu_time: 0.0,
+            speed: 0.0,
+            gear: 0,
+            yaw: 0.0,
+            position: Vector::default(),
+            distance: 0.0,
+            fuel: 1.0,
+            tire_temp: 70.0,
+            lap: 0,
+            last_lap: 0.0,
+            best_lap: 0.0,
+            race_position: 0,
+            next_overtake: 0.0,
+        };
+        sim.start_race();
+        sim
+    }
+
+    fn start_race(&mut self) {
+        self.race_time = 0.0;
+        self.speed = 0.0;
+        self.gear = 0;
+        self.yaw = 0.0;
+        self.position = Vector::default();
+        self.distance = 0.0;
+        self.fuel = 1.0;
+        self.tire_temp = 70.0;
+        self.lap = 0;
+        self.last_lap = 0.0;
+        self.best_lap = 0.0;
+        self.race_position = (self.random() % CARS as u64) as u8 + 1;
+        self.next_overtake = 3.0;
+    }
+
+    fn random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+
+    /// Advances the simulation by `dt` seconds and returns the datagram the game would send.
+    fn step(&mut self, dt: f32) -> Horizon4Datagram {
+        self.timestamp += dt * 1000.0;
+
+        if self.menu_time > 0.0 {
+            self.menu_time -= dt;
+            if self.menu_time <= 0.0 {
+                self.start_race();
+            }
+            // The game sends empty datagrams while the race is off.
+            return datagram(
+                Sled {
+                    timestamp_ms: self.timestamp as u32,
+                    ..Default::default()
+                },
+                Dash::default(),
+            );
+        }
+
+        self.race_time += dt;
+        let lap_time = self.race_time - self.lap as f32 * LAP_SECONDS;
+        if lap_time >= LAP_SECONDS {
+            self.lap += 1;
+            // Laps take the same time on this track, so make them differ a little.
+            self.last_lap = LAP_SECONDS + (self.random() % 1000) as f32 / 1000.0;
+            if self.best_lap == 0.0 || self.last_lap < self.best_lap {
+                self.best_lap = self.last_lap;
+            }
+            if self.lap == RACE_LAPS {
+                self.menu_time = MENU_SECONDS;
+            }
+        }
+
+        self.overtake();
+
+        let phase = Phase::at(lap_time);
+        let (throttle, brake, steer, rumble) = match phase {
+            Phase::Straight => (1.0, 0.0, 0.0, false),
+            Phase::Braking(direction) => (0.0, 1.0, 0.4 * direction, false),
+            Phase::Exit(direction) => (0.3, 0.0, direction, true),
+        };
+
+        let ratio = GEAR_RATIOS[self.gear];
+        let drag = 0.0008 * self.speed * self.speed;
+        let acceleration = throttle * 4.0 * ratio / 1.6 - brake * 9.0 - drag;
+        self.speed = (self.speed + acceleration * dt).max(0.0);
+
+        let rpm = self.speed * ratio * RPM_PER_SPEED;
+        let mut clutch = 0;
+        if rpm > UPSHIFT_RPM && self.gear + 1 < GEAR_RATIOS.len() {
+            self.gear += 1;
+            clutch = 255;
+        } else if rpm < DOWNSHIFT_RPM && self.gear > 0 {
+            self.gear -= 1;
+            clutch = 255;
+        }
+        let rpm = (self.speed * GEAR_RATIOS[self.gear] * RPM_PER_SPEED).clamp(IDLE_RPM, MAX_RPM);
+
+        let yaw_rate = steer * self.speed / 40.0;
+        self.yaw = (self.yaw + yaw_rate * dt) % (2.0 * PI);
+        self.position.x += self.yaw.sin() * self.speed * dt;
+        self.position.z += self.yaw.cos() * self.speed * dt;
+        self.distance += self.speed * dt;
+        self.fuel = (self.fuel - 0.0005 * throttle * dt).max(0.0);
+
+        // Tires heat up in the corners and cool down on the straights.
+        let target_temp = 80.0 + 40.0 * (brake + steer.abs());
+        self.tire_temp += (target_temp - self.tire_temp) * 0.2 * dt;
+
+        let torque = 400.0 * throttle;
+        let wheel_speed = self.speed / WHEEL_RADIUS;
+        let bump = (self.timestamp / 50.0).sin() * if rumble { 0.2 } else { 0.02 };
+        // The outside wheels run over the rumble strips.
+        let (left_rumble, right_rumble) = match phase {
+            Phase::Exit(direction) if direction > 0.0 => (1, 0),
+            Phase::Exit(_) => (0, 1),
+            _ => (0, 0),
+        };
+
+        let sled = Sled {
+            is_race_on: 1,
+            timestamp_ms: self.timestamp as u32,
+            engine_max_rpm: MAX_RPM,
+            engine_idle_rpm: IDLE_RPM,
+            current_engine_rpm: rpm,
+            acceleration: Vector {
+                x: yaw_rate * self.speed,
+                y: 0.0,
+                z: acceleration,
+            },
+            velocity: Vector {
+                x: 0.0,
+                y: 0.0,
+                z: self.speed,
+            },
+            angular_velocity: Vector {
+                x: 0.0,
+                y: yaw_rate,
+                z: 0.0,
+            },
+            yaw: self.yaw,
+            pitch: 0.0,
+            roll: 0.0,
+            normalized_suspension_travel: quad(0.5 + bump),
+            wheel_rotation_speed: quad(wheel_speed),
+            wheel_on_rumble_strip: Quad {
+                front_left: left_rumble,
+                front_right: right_rumble,
+                rear_left: left_rumble,
+                rear_right: right_rumble,
+            },
+            surface_rumble: Quad {
+                front_left: left_rumble as f32 * 0.8,
+                front_right: right_rumble as f32 * 0.8,
+                rear_left: left_rumble as f32 * 0.8,
+                rear_right: right_rumble as f32 * 0.8,
+            },
+            tire_slip_angle: quad(steer * 0.1),
+            tire_combined_slip: quad(steer.abs() * 0.3 + brake * 0.2),
+            suspension_travel_meters: quad(0.05 + bump * 0.1),
+            car_ordinal: 2032,
+            car_class: 5,
+            car_performance_index: 800,
+            drivetrain_type: 1,
+            num_cylinders: 8,
+            ..Default::default()
+        };
+
+        let dash = Dash {
+            position: self.position,
+            speed: self.speed,
+            power: torque * rpm * 2.0 * PI / 60.0,
+            torque,
+            tire_temp: quad(self.tire_temp),
+            boost: 0.0,
+            fuel: self.fuel,
+            distance_traveled: self.distance,
+            best_lap: self.best_lap,
+            last_lap: self.last_lap,
+            current_lap: lap_time,
+            current_race_time: self.race_time,
+            lap_number: self.lap,
+            race_position: self.race_position,
+            accel: (throttle * 255.0) as u8,
+            brake: (brake * 255.0) as u8,
+            clutch,
+            hand_brake: 0,
+            gear: self.gear as u8 + 1,
+            steer: (steer * 127.0) as i8,
+            normalized_driving_line: 0,
+            normalized_ai_brake_difference: 0,
+        };
+
+        datagram(sled, dash)
+    }
+
+    /// Overtakes happen every few seconds.
+    fn overtake(&mut self) {
+        if self.race_time < self.next_overtake {
+            return;
+        }
+
+        self.next_overtake = self.race_time + 3.0 + (self.random() % 5) as f32;
+        if self.random() % 2 == 0 {
+            self.race_position = self.race_position.saturating_sub(1).max(1);
+        } else {
+            self.race_position = (self.race_position + 1).min(CARS);
+        }
+    }
+}
+
+fn quad<T: Copy>(value: T) -> Quad<T> {
+    Quad {
+        front_left: value,
+        front_right: value,
+        rear_left: value,
+        rear_right: value,
+    }
+}
+
+fn datagram(sled: Sled, dash: Dash) -> Horizon4Datagram {
+    Horizon4Datagram {
+        sled,
+        unknown1: [0x28, 0, 0, 0],
+        unknown2: 0.0,
+        unknown3: 0.0,
+        dash,
+        unknown4: 0,
+    }
+}
+
+/// The most datagrams a second that can be sent.
+const MAX_RATE: f32 = 10_000.0;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let matches = clap::App::new("forza-sim")
+        .about("Sends made-up Forza Horizon 4 telemetry, for trying things out without the game")
+        .arg(
+            Arg::with_name("target")
+                .short("t")
+                .long("target")
+                .default_value("127.0.0.1:18733"),
+        )
+        .arg(
+            Arg::with_name("rate")
+                .short("r")
+                .long("rate")
+                .help("Datagrams per second")
+                .default_value("60"),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .short("s")
+                .long("seed")
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("duration")
+                .short("d")
+                .long("duration")
+                .help("Seconds to run for, or until interrupted if omitted")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let target = matches.value_of("target").unwrap();
+    let rate: f32 = matches.value_of("rate").unwrap().parse()?;
+    // Much faster and the time between datagrams rounds down to nothing.
+    if !(rate > 0.0 && rate <= MAX_RATE) {
+        return Err(format!("the rate must be positive and at most {}", MAX_RATE).into());
+    }
+    let seed = matches.value_of("seed").unwrap().parse()?;
+    let duration = match matches.value_of("duration") {
+        Some(duration) => Some(
+            Duration::try_from_secs_f32(duration.parse()?)
+                .map_err(|_| "the duration must be a non-negative number of seconds")?,
+        ),
+        None => None,
+    };
+
+    let mut socket = UdpSocket::bind("0.0.0.0:0").await?;
+    let mut sim = Sim::new(seed);
+    let dt = 1.0 / rate;
+    let mut ticks = interval(Duration::from_secs_f32(dt));
+    let start = Instant::now();
+    let ctrl_c = ctrl_c();
+    pin_mut!(ctrl_c);
+
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {},
+            _ = &mut ctrl_c => break,
+        }
+        if duration.is_some_and(|duration| start.elapsed() >= duration) {
+            break;
+        }
+
+        socket.send_to(&sim.step(dt).to_bytes(), target).await?;
+    }
+
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use std::env;
 use std::mem::size_of;
 
+use forza::Datagram;
 use futures_util::pin_mut;
 use tokio::stream::StreamExt;
 
@@ -9,8 +10,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     assert_eq!(232, size_of::<forza::Sled>());
     assert_eq!(79, size_of::<forza::Dash>());
     assert_eq!(324, size_of::<forza::Horizon4Datagram>());
+    assert_eq!(232, size_of::<forza::Motorsport7SledDatagram>());
+    assert_eq!(311, size_of::<forza::Motorsport7DashDatagram>());
+    assert_eq!(324, size_of::<forza::Horizon5Datagram>());
+    assert_eq!(331, size_of::<forza::Motorsport2023Datagram>());
+    assert_eq!(324, forza::Horizon4Datagram::SIZE);
+    assert_eq!(232, forza::Motorsport7SledDatagram::SIZE);
+    assert_eq!(311, forza::Motorsport7DashDatagram::SIZE);
+    assert_eq!(324, forza::Horizon5Datagram::SIZE);
+    assert_eq!(331, forza::Motorsport2023Datagram::SIZE);
 
-    let server_ip = env::args().skip(1).next().unwrap();
+    let server_ip = env::args().nth(1).unwrap();
     let server_addr = server_ip + ":8000";
 
     let stream = forza::horizon4(server_addr).await?;
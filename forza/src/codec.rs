@@ -0,0 +1,433 @@
+//! Conversion between datagrams and their wire format. Every field is read and written as little
+//! endian, one at a time, so that neither the host's byte order nor the layout of the datagram
+//! types matters.
+
+use std::convert::TryInto;
+
+use crate::{datagram::*, error::DecodeError};
+
+/// A datagram with a fixed-size wire format.
+pub trait Datagram: Sized {
+    /// The size of the datagram on the wire, in bytes.
+    const SIZE: usize;
+
+    /// Decodes a datagram, which must be exactly `SIZE` bytes long.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError>;
+
+    /// Encodes the datagram into its `SIZE` byte wire format.
+    fn to_bytes(&self) -> Vec<u8>;
+}
+
+/// A value with a fixed-size, little endian wire format.
+trait Field: Sized {
+    const SIZE: usize;
+
+    /// Reads the value from the front of `bytes`, which must hold at least `SIZE` bytes.
+    fn read(bytes: &mut &[u8]) -> Self;
+
+    fn write(&self, out: &mut Vec<u8>);
+}
+
+fn take<const N: usize>(bytes: &mut &[u8]) -> [u8; N] {
+    let (head, tail) = bytes.split_at(N);
+    *bytes = tail;
+    head.try_into().unwrap()
+}
+
+macro_rules! primitive_fields {
+    ($($ty:ty),*) => {
+        $(
+            impl Field for $ty {
+                const SIZE: usize = std::mem::size_of::<$ty>();
+
+                fn read(bytes: &mut &[u8]) -> Self {
+                    <$ty>::from_le_bytes(take(bytes))
+                }
+
+                fn write(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+            }
+        )*
+    };
+}
+
+primitive_fields!(i8, u8, u16, i32, u32, f32);
+
+impl<const N: usize> Field for [u8; N] {
+    const SIZE: usize = N;
+
+    fn read(bytes: &mut &[u8]) -> Self {
+        take(bytes)
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+}
+
+/// Implements `Field` for a struct by reading and writing its fields in order. Fields are copied
+/// out before being written so that this also works for packed structs.
+macro_rules! struct_field {
+    ($name:ident $(<$param:ident>)? { $($field:ident: $ty:ty),* $(,)? }) => {
+        impl$(<$param: Field + Copy>)? Field for $name$(<$param>)? {
+            const SIZE: usize = 0 $(+ <$ty as Field>::SIZE)*;
+
+            fn read(bytes: &mut &[u8]) -> Self {
+                Self {
+                    $($field: <$ty as Field>::read(bytes),)*
+                }
+            }
+
+            fn write(&self, out: &mut Vec<u8>) {
+                $(
+                    let $field: $ty = self.$field;
+                    $field.write(out);
+                )*
+            }
+        }
+    };
+}
+
+struct_field!(Vector<T> { x: T, y: T, z: T });
+
+struct_field!(Quad<T> {
+    front_left: T,
+    front_right: T,
+    rear_left: T,
+    rear_right: T,
+});
+
+struct_field!(Sled {
+    is_race_on: i32,
+    timestamp_ms: u32,
+    engine_max_rpm: f32,
+    engine_idle_rpm: f32,
+    current_engine_rpm: f32,
+    acceleration: Vector<f32>,
+    velocity: Vector<f32>,
+    angular_velocity: Vector<f32>,
+    yaw: f32,
+    pitch: f32,
+    roll: f32,
+    normalized_suspension_travel: Quad<f32>,
+    tire_split_ratio: Quad<f32>,
+    wheel_rotation_speed: Quad<f32>,
+    wheel_on_rumble_strip: Quad<i32>,
+    wheel_in_puddle_depth: Quad<f32>,
+    surface_rumble: Quad<f32>,
+    tire_slip_angle: Quad<f32>,
+    tire_combined_slip: Quad<f32>,
+    suspension_travel_meters: Quad<f32>,
+    car_ordinal: i32,
+    car_class: i32,
+    car_performance_index: i32,
+    drivetrain_type: i32,
+    num_cylinders: i32,
+});
+
+struct_field!(Dash {
+    position: Vector<f32>,
+    speed: f32,
+    power: f32,
+    torque: f32,
+    tire_temp: Quad<f32>,
+    boost: f32,
+    fuel: f32,
+    distance_traveled: f32,
+    best_lap: f32,
+    last_lap: f32,
+    current_lap: f32,
+    current_race_time: f32,
+    lap_number: u16,
+    race_position: u8,
+    accel: u8,
+    brake: u8,
+    clutch: u8,
+    hand_brake: u8,
+    gear: u8,
+    steer: i8,
+    normalized_driving_line: i8,
+    normalized_ai_brake_difference: i8,
+});
+
+struct_field!(Horizon4Datagram {
+    sled: Sled,
+    unknown1: [u8; 4],
+    unknown2: f32,
+    unknown3: f32,
+    dash: Dash,
+    unknown4: i8,
+});
+
+struct_field!(Horizon5Datagram {
+    sled: Sled,
+    horizon_placeholder: [u8; 12],
+    dash: Dash,
+    padding: u8,
+});
+
+struct_field!(Motorsport7SledDatagram { sled: Sled });
+
+struct_field!(Motorsport7DashDatagram {
+    sled: Sled,
+    dash: Dash,
+});
+
+struct_field!(Motorsport2023Datagram {
+    sled: Sled,
+    dash: Dash,
+    tire_wear: Quad<f32>,
+    track_ordinal: i32,
+});
+
+macro_rules! datagrams {
+    ($($ty:ty),*) => {
+        $(
+            impl Datagram for $ty {
+                const SIZE: usize = <$ty as Field>::SIZE;
+
+                fn from_bytes(mut bytes: &[u8]) -> Result<Self, DecodeError> {
+                    if bytes.len() != <Self as Datagram>::SIZE {
+                        return Err(DecodeError::WrongSize(<Self as Datagram>::SIZE, bytes.len()));
+                    }
+
+                    Ok(Field::read(&mut bytes))
+                }
+
+                fn to_bytes(&self) -> Vec<u8> {
+                    let mut out = Vec::with_capacity(<Self as Datagram>::SIZE);
+                    Field::write(self, &mut out);
+                    out
+                }
+            }
+        )*
+    };
+}
+
+datagrams!(
+    Horizon4Datagram,
+    Horizon5Datagram,
+    Motorsport7SledDatagram,
+    Motorsport7DashDatagram,
+    Motorsport2023Datagram
+);
+
+#[cfg(test)]
+mod tests {
+    use super::Datagram;
+    use crate::{
+        datagram::*,
+        error::DecodeError,
+        telemetry::{Game, Telemetry},
+    };
+
+    /// Bytes that differ from their neighbours, so that a field read from the wrong offset or in
+    /// the wrong order doesn't go unnoticed.
+    fn pattern(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i * 7 + 3) as u8).collect()
+    }
+
+    fn put(bytes: &mut [u8], offset: usize, value: &[u8]) {
+        bytes[offset..offset + value.len()].copy_from_slice(value);
+    }
+
+    /// A sled with a value in its first, last and a few of its middle fields.
+    fn sled_bytes(bytes: &mut [u8]) {
+        put(bytes, 0, &1i32.to_le_bytes());
+        put(bytes, 4, &123_456u32.to_le_bytes());
+        put(bytes, 8, &8000f32.to_le_bytes());
+        put(bytes, 12, &1000f32.to_le_bytes());
+        put(bytes, 16, &4500f32.to_le_bytes());
+        put(bytes, 56, &(-1.5f32).to_le_bytes());
+        put(bytes, 128, &1i32.to_le_bytes());
+        put(bytes, 228, &8i32.to_le_bytes());
+    }
+
+    fn check_sled(sled: &Sled) {
+        assert_eq!(sled.is_race_on, 1);
+        assert_eq!(sled.timestamp_ms, 123_456);
+        assert_eq!(sled.engine_max_rpm, 8000.0);
+        assert_eq!(sled.engine_idle_rpm, 1000.0);
+        assert_eq!(sled.current_engine_rpm, 4500.0);
+        assert_eq!(sled.yaw, -1.5);
+        assert_eq!(sled.wheel_on_rumble_strip.rear_right, 1);
+        assert_eq!(sled.num_cylinders, 8);
+    }
+
+    /// A dash starting at `offset`, with a value in its first, last and a few of its middle fields.
+    fn dash_bytes(bytes: &mut [u8], offset: usize) {
+        put(bytes, offset, &10f32.to_le_bytes());
+        put(bytes, offset + 12, &55.5f32.to_le_bytes());
+        put(bytes, offset + 68, &3u16.to_le_bytes());
+        put(
+            bytes,
+            offset + 70,
+            &[2, 255, 128, 0, 64, 4, (-127i8) as u8, 100, (-5i8) as u8],
+        );
+    }
+
+    fn check_dash(dash: Dash) {
+        assert_eq!({ dash.position.x }, 10.0);
+        assert_eq!({ dash.speed }, 55.5);
+        assert_eq!({ dash.lap_number }, 3);
+        assert_eq!(dash.race_position, 2);
+        assert_eq!(dash.accel, 255);
+        assert_eq!(dash.brake, 128);
+        assert_eq!(dash.clutch, 0);
+        assert_eq!(dash.hand_brake, 64);
+        assert_eq!(dash.gear, 4);
+        assert_eq!(dash.steer, -127);
+        assert_eq!(dash.normalized_driving_line, 100);
+        assert_eq!(dash.normalized_ai_brake_difference, -5);
+    }
+
+    fn round_trip<D: Datagram>() {
+        let bytes = pattern(D::SIZE);
+        assert_eq!(D::from_bytes(&bytes).unwrap().to_bytes(), bytes);
+    }
+
+    fn check_wrong_sizes<D: Datagram>() {
+        for size in [0, D::SIZE - 1, D::SIZE + 1] {
+            match D::from_bytes(&pattern(size)) {
+                Err(DecodeError::WrongSize(expected, actual)) => {
+                    assert_eq!((expected, actual), (D::SIZE, size))
+                }
+                other => panic!("expected a WrongSize error, got {:?}", other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn sizes_match_the_games() {
+        assert_eq!(Horizon4Datagram::SIZE, 324);
+        assert_eq!(Horizon5Datagram::SIZE, 324);
+        assert_eq!(Motorsport7SledDatagram::SIZE, 232);
+        assert_eq!(Motorsport7DashDatagram::SIZE, 311);
+        assert_eq!(Motorsport2023Datagram::SIZE, 331);
+    }
+
+    #[test]
+    fn datagrams_round_trip() {
+        round_trip::<Horizon4Datagram>();
+        round_trip::<Horizon5Datagram>();
+        round_trip::<Motorsport7SledDatagram>();
+        round_trip::<Motorsport7DashDatagram>();
+        round_trip::<Motorsport2023Datagram>();
+    }
+
+    #[test]
+    fn decodes_horizon4() {
+        let mut bytes = vec![0; Horizon4Datagram::SIZE];
+        sled_bytes(&mut bytes);
+        put(&mut bytes, 232, &[40, 1, 2, 3]);
+        dash_bytes(&mut bytes, 244);
+        put(&mut bytes, 323, &[(-1i8) as u8]);
+
+        let datagram = Horizon4Datagram::from_bytes(&bytes).unwrap();
+        check_sled(&datagram.sled);
+        assert_eq!(datagram.unknown1, [40, 1, 2, 3]);
+        check_dash(datagram.dash);
+        assert_eq!(datagram.unknown4, -1);
+        assert_eq!(datagram.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn decodes_horizon5() {
+        let mut bytes = vec![0; Horizon5Datagram::SIZE];
+        sled_bytes(&mut bytes);
+        put(&mut bytes, 232, &[9; 12]);
+        dash_bytes(&mut bytes, 244);
+        put(&mut bytes, 323, &[7]);
+
+        let datagram = Horizon5Datagram::from_bytes(&bytes).unwrap();
+        check_sled(&datagram.sled);
+        assert_eq!(datagram.horizon_placeholder, [9; 12]);
+        check_dash(datagram.dash);
+        assert_eq!(datagram.padding, 7);
+        assert_eq!(datagram.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn decodes_motorsport7_sled() {
+        let mut bytes = vec![0; Motorsport7SledDatagram::SIZE];
+        sled_bytes(&mut bytes);
+
+        let datagram = Motorsport7SledDatagram::from_bytes(&bytes).unwrap();
+        check_sled(&datagram.sled);
+        assert_eq!(datagram.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn decodes_motorsport7_dash() {
+        let mut bytes = vec![0; Motorsport7DashDatagram::SIZE];
+        sled_bytes(&mut bytes);
+        dash_bytes(&mut bytes, 232);
+
+        let datagram = Motorsport7DashDatagram::from_bytes(&bytes).unwrap();
+        check_sled(&{ datagram.sled });
+        check_dash(datagram.dash);
+        assert_eq!(datagram.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn decodes_motorsport2023() {
+        let mut bytes = vec![0; Motorsport2023Datagram::SIZE];
+        sled_bytes(&mut bytes);
+        dash_bytes(&mut bytes, 232);
+        put(&mut bytes, 311, &0.25f32.to_le_bytes());
+        put(&mut bytes, 323, &0.75f32.to_le_bytes());
+        put(&mut bytes, 327, &(-3i32).to_le_bytes());
+
+        let datagram = Motorsport2023Datagram::from_bytes(&bytes).unwrap();
+        check_sled(&{ datagram.sled });
+        check_dash(datagram.dash);
+        let tire_wear = datagram.tire_wear;
+        assert_eq!(tire_wear.front_left, 0.25);
+        assert_eq!(tire_wear.rear_right, 0.75);
+        assert_eq!({ datagram.track_ordinal }, -3);
+        assert_eq!(datagram.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn truncated_and_oversized_datagrams_are_the_wrong_size() {
+        check_wrong_sizes::<Horizon4Datagram>();
+        check_wrong_sizes::<Horizon5Datagram>();
+        check_wrong_sizes::<Motorsport7SledDatagram>();
+        check_wrong_sizes::<Motorsport7DashDatagram>();
+        check_wrong_sizes::<Motorsport2023Datagram>();
+    }
+
+    #[test]
+    fn datagrams_of_no_format_are_an_unknown_size() {
+        for size in [0, 100, 231, 310, 323, 330, 512] {
+            match Telemetry::decode(&pattern(size)) {
+                Err(DecodeError::UnknownSize(actual)) => assert_eq!(actual, size),
+                other => panic!("expected an UnknownSize error, got {:?}", other.map(|_| ())),
+            }
+        }
+        match Telemetry::decode_game(Game::Motorsport7, &pattern(310)) {
+            Err(DecodeError::UnknownSize(310)) => {}
+            other => panic!("expected an UnknownSize error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn datagrams_are_told_apart_by_size() {
+        let games = [
+            (Horizon4Datagram::SIZE, Game::Horizon4, true),
+            (Motorsport7SledDatagram::SIZE, Game::Motorsport7, false),
+            (Motorsport7DashDatagram::SIZE, Game::Motorsport7, true),
+            (Motorsport2023Datagram::SIZE, Game::Motorsport2023, true),
+        ];
+        for (size, game, has_dash) in games {
+            let mut bytes = pattern(size);
+            sled_bytes(&mut bytes);
+
+            let telemetry = Telemetry::decode(&bytes).unwrap();
+            assert_eq!(telemetry.game, game);
+            assert_eq!(telemetry.dash.is_some(), has_dash);
+            assert_eq!(telemetry.sled.current_engine_rpm, 4500.0);
+        }
+    }
+}
@@ -1,7 +1,7 @@
-use serde::{Deserialize, Serialize, Serializer};
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Vector<T> {
     pub x: T,
     pub y: T,
@@ -9,7 +9,7 @@ pub struct Vector<T> {
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Quad<T> {
     pub front_left: T,
     pub front_right: T,
@@ -18,7 +18,7 @@ pub struct Quad<T> {
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Sled {
     pub is_race_on: i32,
     pub timestamp_ms: u32,
@@ -79,7 +79,7 @@ struct DashUnpacked {
 }
 
 #[repr(C, packed)]
-#[derive(Copy, Clone, Debug, Deserialize)]
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
 pub struct Dash {
     pub position: Vector<f32>,
     // m/s
@@ -154,3 +154,77 @@ pub struct Horizon4Datagram {
     pub dash: Dash,
     pub unknown4: i8,
 }
+
+/// The "Sled" Data Out format of Forza Motorsport 7.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Motorsport7SledDatagram {
+    pub sled: Sled,
+}
+
+/// The "Car Dash" Data Out format of Forza Motorsport 7.
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug, Deserialize)]
+pub struct Motorsport7DashDatagram {
+    pub sled: Sled,
+    pub dash: Dash,
+}
+
+impl Serialize for Motorsport7DashDatagram {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let (sled, dash) = (self.sled, self.dash);
+
+        let mut state = serializer.serialize_struct("Motorsport7DashDatagram", 2)?;
+        state.serialize_field("sled", &sled)?;
+        state.serialize_field("dash", &dash)?;
+        state.end()
+    }
+}
+
+/// Forza Motorsport 7 sends one of two formats, depending on the game settings.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Motorsport7Datagram {
+    Sled(Motorsport7SledDatagram),
+    Dash(Motorsport7DashDatagram),
+}
+
+/// The Data Out format of Forza Horizon 5. It has the same size as Forza Horizon 4's, with the
+/// horizon-specific bytes between the sled and the dash left undocumented.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Horizon5Datagram {
+    pub sled: Sled,
+    pub horizon_placeholder: [u8; 12],
+    pub dash: Dash,
+    pub padding: u8,
+}
+
+/// The "Car Dash" Data Out format of Forza Motorsport (2023), which extends Forza Motorsport 7's.
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug, Deserialize)]
+pub struct Motorsport2023Datagram {
+    pub sled: Sled,
+    pub dash: Dash,
+    pub tire_wear: Quad<f32>,
+    pub track_ordinal: i32,
+}
+
+impl Serialize for Motorsport2023Datagram {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let (sled, dash, tire_wear, track_ordinal) =
+            (self.sled, self.dash, self.tire_wear, self.track_ordinal);
+
+        let mut state = serializer.serialize_struct("Motorsport2023Datagram", 4)?;
+        state.serialize_field("sled", &sled)?;
+        state.serialize_field("dash", &dash)?;
+        state.serialize_field("tire_wear", &tire_wear)?;
+        state.serialize_field("track_ordinal", &track_ordinal)?;
+        state.end()
+    }
+}
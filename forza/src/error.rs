@@ -0,0 +1,41 @@
+use std::{io, net::SocketAddr};
+
+use quick_error::quick_error;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+quick_error! {
+    /// A datagram that couldn't be decoded.
+    #[derive(Debug)]
+    pub enum DecodeError {
+        /// The datagram doesn't have the size of its format.
+        WrongSize(expected: usize, actual: usize) {
+            display("expected a {} byte datagram, got {} bytes", expected, actual)
+        }
+        /// The datagram doesn't have the size of any known format.
+        UnknownSize(actual: usize) {
+            display("unrecognized datagram size: {} bytes", actual)
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum Error {
+        Io(err: io::Error) {
+            from()
+            display("{}", err)
+            cause(err)
+        }
+        Decode(err: DecodeError) {
+            from()
+            display("{}", err)
+            cause(err)
+        }
+        /// A datagram couldn't be relayed to a target.
+        Relay(target: SocketAddr, err: io::Error) {
+            display("couldn't relay datagram to {}: {}", target, err)
+            cause(err)
+        }
+    }
+}
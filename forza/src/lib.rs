@@ -1,41 +1,198 @@
-use std::{
-    mem::{size_of_val, MaybeUninit},
-    slice,
-};
+#![recursion_limit = "256"]
+
+use std::{net::SocketAddr, pin::Pin};
 
 use tokio::{
     io,
     net::{ToSocketAddrs, UdpSocket},
+    stream::StreamExt,
 };
 
 use futures_core::Stream;
 
+mod codec;
 mod datagram;
+mod error;
+mod recording;
+mod telemetry;
 
+pub use codec::*;
 pub use datagram::*;
+pub use error::*;
+pub use recording::*;
+pub use telemetry::*;
+
+/// Receives datagrams from any supported game, telling the formats apart by their size.
+///
+/// Datagrams of an unexpected size are yielded as `Error::Decode` errors, after which the stream
+/// carries on, so that switching games doesn't end the session.
+pub async fn listen<A: ToSocketAddrs>(
+    addr: A,
+) -> io::Result<impl Stream<Item = Result<Telemetry>>> {
+    Ok(listen_raw(addr, None)
+        .await?
+        .map(|datagram| datagram.map(|(telemetry, _)| telemetry)))
+}
+
+/// Receives datagrams like `listen`, or like `telemetry` if a `game` is given, along with the bytes
+/// they were decoded from. This lets recordings keep exactly what the game sent.
+pub async fn listen_raw<A: ToSocketAddrs>(
+    addr: A,
+    game: Option<Game>,
+) -> io::Result<impl Stream<Item = Result<(Telemetry, Vec<u8>)>>> {
+    let mut socket = UdpSocket::bind(addr).await?;
+    Ok(async_stream::stream! {
+        // Big enough for any format, with room to spare to detect oversized packets.
+        let mut buf = [0u8; 512];
+        loop {
+            let buf = match socket.recv_from(&mut buf).await {
+                Ok((amt, _src)) => &buf[..amt],
+                Err(e) => {
+                    yield Err(e.into());
+                    break;
+                }
+            };
+
+            yield match game {
+                Some(game) => Telemetry::decode_game(game, buf),
+                None => Telemetry::decode(buf),
+            }
+            .map(|telemetry| (telemetry, buf.to_vec()))
+            .map_err(Into::into);
+        }
+    })
+}
 
 pub async fn horizon4<A: ToSocketAddrs>(
     addr: A,
-) -> io::Result<impl Stream<Item = io::Result<datagram::Horizon4Datagram>>> {
+) -> io::Result<impl Stream<Item = Result<datagram::Horizon4Datagram>>> {
+    receive(addr).await
+}
+
+pub async fn horizon5<A: ToSocketAddrs>(
+    addr: A,
+) -> io::Result<impl Stream<Item = Result<datagram::Horizon5Datagram>>> {
+    receive(addr).await
+}
+
+/// Receives Forza Motorsport 7 datagrams in either the "Sled" or the "Car Dash" format.
+pub async fn motorsport7<A: ToSocketAddrs>(
+    addr: A,
+) -> io::Result<impl Stream<Item = Result<datagram::Motorsport7Datagram>>> {
+    let mut socket = UdpSocket::bind(addr).await?;
+    Ok(async_stream::stream! {
+        // Big enough for either format, with room to spare to detect oversized packets.
+        let mut buf = [0u8; 512];
+        loop {
+            let amt = match socket.recv_from(&mut buf).await {
+                Ok((amt, _src)) => amt,
+                Err(e) => {
+                    yield Err(e.into());
+                    break;
+                }
+            };
+            let buf = &buf[..amt];
+
+            yield match amt {
+                Motorsport7SledDatagram::SIZE => {
+                    Motorsport7SledDatagram::from_bytes(buf).map(Motorsport7Datagram::Sled)
+                }
+                Motorsport7DashDatagram::SIZE => {
+                    Motorsport7DashDatagram::from_bytes(buf).map(Motorsport7Datagram::Dash)
+                }
+                amt => Err(DecodeError::UnknownSize(amt)),
+            }
+            .map_err(Into::into);
+        }
+    })
+}
+
+pub async fn motorsport2023<A: ToSocketAddrs>(
+    addr: A,
+) -> io::Result<impl Stream<Item = Result<datagram::Motorsport2023Datagram>>> {
+    receive(addr).await
+}
+
+/// Receives datagrams from the given game, normalized to `Telemetry`.
+pub async fn telemetry<'a, A: 'a + ToSocketAddrs>(
+    game: Game,
+    addr: A,
+) -> io::Result<Pin<Box<dyn 'a + Stream<Item = Result<Telemetry>>>>> {
+    Ok(match game {
+        Game::Horizon4 => normalize(horizon4(addr).await?),
+        Game::Horizon5 => normalize(horizon5(addr).await?),
+        Game::Motorsport7 => normalize(motorsport7(addr).await?),
+        Game::Motorsport2023 => normalize(motorsport2023(addr).await?),
+    })
+}
+
+/// Receives datagrams like `listen`, or like `telemetry` if a `game` is given, and sends every one
+/// of them on to each of the `targets` unchanged, including the ones that can't be decoded. This
+/// lets other tools consume the single feed the game sends.
+///
+/// A datagram that can't be sent to a target is yielded as an `Error::Relay` error, after which the
+/// stream carries on.
+pub async fn relay<A: ToSocketAddrs>(
+    addr: A,
+    game: Option<Game>,
+    targets: Vec<SocketAddr>,
+) -> io::Result<impl Stream<Item = Result<Telemetry>>> {
     let mut socket = UdpSocket::bind(addr).await?;
-    Ok(async_stream::try_stream! {
+    Ok(async_stream::stream! {
+        // Big enough for any format, with room to spare to detect oversized packets.
+        let mut buf = [0u8; 512];
         loop {
-            let datagram = unsafe {
-                // The following is safe because 1) datagram has no padding, 2) all datagram members
-                // can hold any bit pattern, and 3) we verify that we received the exact datagram
-                // size - i.e. all unitialized data is over-written
-                let mut datagram = MaybeUninit::uninit();
+            let amt = match socket.recv_from(&mut buf).await {
+                Ok((amt, _src)) => amt,
+                // Windows reports targets that aren't listening on the next receive.
+                Err(e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+                Err(e) => {
+                    yield Err(e.into());
+                    break;
+                }
+            };
+            let buf = &buf[..amt];
 
-                let mut buf =
-                    slice::from_raw_parts_mut(datagram.as_mut_ptr() as *mut _, size_of_val(&datagram));
+            for &target in &targets {
+                if let Err(e) = socket.send_to(buf, target).await {
+                    yield Err(Error::Relay(target, e));
+                }
+            }
 
-                let (amt, _src) = socket.recv_from(&mut buf).await?;
-                assert_eq!(amt, size_of_val(&datagram));
+            yield match game {
+                Some(game) => Telemetry::decode_game(game, buf),
+                None => Telemetry::decode(buf),
+            }
+            .map_err(Into::into);
+        }
+    })
+}
+
+fn normalize<'a, D: Into<Telemetry>>(
+    stream: impl 'a + Stream<Item = Result<D>>,
+) -> Pin<Box<dyn 'a + Stream<Item = Result<Telemetry>>>> {
+    Box::pin(stream.map(|datagram| datagram.map(Into::into)))
+}
 
-                datagram.assume_init()
+/// Receives datagrams of a single, fixed-size format. Packets of any other size are yielded as
+/// `Error::Decode` errors.
+async fn receive<A: ToSocketAddrs, D: Datagram + Unpin>(
+    addr: A,
+) -> io::Result<impl Stream<Item = Result<D>>> {
+    let mut socket = UdpSocket::bind(addr).await?;
+    Ok(async_stream::stream! {
+        // Leave room to spare to detect oversized packets.
+        let mut buf = vec![0u8; D::SIZE + 1];
+        loop {
+            let amt = match socket.recv_from(&mut buf).await {
+                Ok((amt, _src)) => amt,
+                Err(e) => {
+                    yield Err(e.into());
+                    break;
+                }
             };
 
-            yield datagram;
+            yield D::from_bytes(&buf[..amt]).map_err(Into::into);
         }
     })
 }
@@ -51,3 +208,55 @@ pub async fn horizon4<A: ToSocketAddrs>(
 //         })
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::{net::UdpSocket, stream::StreamExt, time::timeout};
+
+    use crate::{Datagram, Motorsport7SledDatagram, Sled};
+
+    #[tokio::test]
+    async fn relay_sends_every_datagram_to_every_target() {
+        // Find a free port for the relay, which doesn't say which one it bound.
+        let relay_addr = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut targets = [
+            UdpSocket::bind("127.0.0.1:0").await.unwrap(),
+            UdpSocket::bind("127.0.0.1:0").await.unwrap(),
+        ];
+        let addrs = targets
+            .iter()
+            .map(|target| target.local_addr().unwrap())
+            .collect();
+
+        let relay = super::relay(relay_addr, None, addrs).await.unwrap();
+        tokio::pin!(relay);
+
+        let datagram = Motorsport7SledDatagram {
+            sled: Sled {
+                timestamp_ms: 42,
+                ..Sled::default()
+            },
+        }
+        .to_bytes();
+        let mut game = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        game.send_to(&datagram, relay_addr).await.unwrap();
+
+        let wait = Duration::from_secs(5);
+        let telemetry = timeout(wait, relay.next()).await.unwrap().unwrap().unwrap();
+        assert_eq!(telemetry.sled.timestamp_ms, 42);
+
+        for target in &mut targets {
+            let mut buf = [0u8; 512];
+            let (amt, _) = timeout(wait, target.recv_from(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(&buf[..amt], &datagram[..]);
+        }
+    }
+}
@@ -0,0 +1,355 @@
+//! The recording container format. All integers are little endian.
+//!
+//! A recording starts with a header:
+//!
+//! | Field       | Type      | Notes                                                       |
+//! |-------------|-----------|-------------------------------------------------------------|
+//! | magic       | `[u8; 8]` | `FORZAREC`                                                  |
+//! | version     | `u16`     | `RECORDING_VERSION`                                         |
+//! | game        | `u8`      | See `game_id`                                               |
+//! | packet size | `u16`     | The size of the game's datagrams, or 0 if it sends several  |
+//! | start time  | `u64`     | Milliseconds since the Unix epoch                           |
+//! | notes       | `u32`     | Length of the notes, followed by the notes as UTF-8         |
+//!
+//! Which is followed by the packets, back to back:
+//!
+//! | Field    | Type   | Notes                                        |
+//! |----------|--------|----------------------------------------------|
+//! | received | `u64`  | Microseconds since the start of the recording |
+//! | length   | `u16`  | Length of the datagram, followed by its bytes |
+//!
+//! If the header has a packet size, every datagram must have that length.
+
+use std::{
+    convert::TryInto,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::telemetry::Game;
+
+/// The first bytes of every recording. Files that don't start with them are legacy raw
+/// recordings, i.e. back-to-back datagrams without any header.
+pub const RECORDING_MAGIC: &[u8; 8] = b"FORZAREC";
+
+/// The version of the container format that is written.
+pub const RECORDING_VERSION: u16 = 1;
+
+#[derive(Clone, Debug)]
+pub struct RecordingHeader {
+    pub version: u16,
+    pub game: Game,
+    /// The size of every datagram in the recording, or `None` if the game sends datagrams of more
+    /// than one size.
+    pub packet_size: Option<usize>,
+    /// The wall-clock time at which the recording started.
+    pub start_time: SystemTime,
+    pub notes: String,
+}
+
+impl RecordingHeader {
+    pub fn new(game: Game, start_time: SystemTime, notes: String) -> Self {
+        Self {
+            version: RECORDING_VERSION,
+            game,
+            packet_size: game.datagram_size(),
+            start_time,
+            notes,
+        }
+    }
+}
+
+/// A datagram along with the time it was received at.
+#[derive(Clone, Debug)]
+pub struct RecordedPacket {
+    /// The time the datagram was received at, since the start of the recording.
+    pub received: Duration,
+    pub datagram: Vec<u8>,
+}
+
+pub struct RecordingWriter<W> {
+    writer: W,
+    packet_size: Option<usize>,
+}
+
+impl<W: AsyncWrite + Unpin> RecordingWriter<W> {
+    /// Starts a recording by writing its header.
+    pub async fn create(mut writer: W, header: &RecordingHeader) -> io::Result<Self> {
+        let start_time = header
+            .start_time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let packet_size = header.packet_size.unwrap_or(0);
+        let packet_size: u16 = packet_size
+            .try_into()
+            .map_err(|_| invalid_data(format!("packet size too large: {}", packet_size)))?;
+        let notes_len: u32 = header
+            .notes
+            .len()
+            .try_into()
+            .map_err(|_| invalid_data("notes too long"))?;
+
+        writer.write_all(RECORDING_MAGIC).await?;
+        writer.write_all(&header.version.to_le_bytes()).await?;
+        writer.write_all(&[game_id(header.game)]).await?;
+        writer.write_all(&packet_size.to_le_bytes()).await?;
+        writer.write_all(&start_time.to_le_bytes()).await?;
+        writer.write_all(&notes_len.to_le_bytes()).await?;
+        writer.write_all(header.notes.as_bytes()).await?;
+
+        Ok(Self {
+            writer,
+            packet_size: header.packet_size,
+        })
+    }
+
+    pub async fn write_packet(&mut self, received: Duration, datagram: &[u8]) -> io::Result<()> {
+        check_packet_size(self.packet_size, datagram.len())?;
+        let len: u16 = datagram
+            .len()
+            .try_into()
+            .map_err(|_| invalid_data(format!("datagram too large: {}", datagram.len())))?;
+
+        self.writer
+            .write_all(&(received.as_micros() as u64).to_le_bytes())
+            .await?;
+        self.writer.write_all(&len.to_le_bytes()).await?;
+        self.writer.write_all(datagram).await
+    }
+
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush().await
+    }
+}
+
+pub struct RecordingReader<R> {
+    reader: R,
+    header: RecordingHeader,
+}
+
+impl<R: AsyncRead + Unpin> RecordingReader<R> {
+    /// Starts reading a recording by reading its header.
+    pub async fn open(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic).await?;
+        if &magic != RECORDING_MAGIC {
+            return Err(invalid_data("not a recording"));
+        }
+
+        let version = u16::from_le_bytes(read_array(&mut reader).await?);
+        if version != RECORDING_VERSION {
+            return Err(invalid_data(format!(
+                "unsupported recording version: {}",
+                version
+            )));
+        }
+
+        let [game] = read_array(&mut reader).await?;
+        let game = game_from_id(game)
+            .ok_or_else(|| invalid_data(format!("unknown game in recording: {}", game)))?;
+        let packet_size = match u16::from_le_bytes(read_array(&mut reader).await?) {
+            0 => None,
+            packet_size => Some(packet_size as usize),
+        };
+        let start_time =
+            UNIX_EPOCH + Duration::from_millis(u64::from_le_bytes(read_array(&mut reader).await?));
+
+        let notes_len = u32::from_le_bytes(read_array(&mut reader).await?);
+        let mut notes = vec![0u8; notes_len as usize];
+        reader.read_exact(&mut notes).await?;
+        let notes = String::from_utf8(notes).map_err(|_| invalid_data("notes aren't UTF-8"))?;
+
+        Ok(Self {
+            reader,
+            header: RecordingHeader {
+                version,
+                game,
+                packet_size,
+                start_time,
+                notes,
+            },
+        })
+    }
+
+    pub fn header(&self) -> &RecordingHeader {
+        &self.header
+    }
+
+    /// Reads the next packet, or `None` at the end of the recording.
+    pub async fn next_packet(&mut self) -> io::Result<Option<RecordedPacket>> {
+        let mut received = [0u8; 8];
+        if self.reader.read(&mut received[..1]).await? == 0 {
+            return Ok(None);
+        }
+
+        self.read_rest_of_packet(received)
+            .await
+            .map(Some)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "recording ends in the middle of a packet",
+                ),
+                _ => e,
+            })
+    }
+
+    async fn read_rest_of_packet(&mut self, mut received: [u8; 8]) -> io::Result<RecordedPacket> {
+        self.reader.read_exact(&mut received[1..]).await?;
+        let received = Duration::from_micros(u64::from_le_bytes(received));
+
+        let len = u16::from_le_bytes(read_array(&mut self.reader).await?);
+        check_packet_size(self.header.packet_size, len as usize)?;
+        let mut datagram = vec![0u8; len as usize];
+        self.reader.read_exact(&mut datagram).await?;
+
+        Ok(RecordedPacket { received, datagram })
+    }
+}
+
+async fn read_array<R: AsyncRead + Unpin, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut array = [0u8; N];
+    reader.read_exact(&mut array).await?;
+    Ok(array)
+}
+
+/// Checks a datagram's length against the packet size of the recording, if it has one.
+fn check_packet_size(packet_size: Option<usize>, len: usize) -> io::Result<()> {
+    match packet_size {
+        Some(packet_size) if len != packet_size => Err(invalid_data(format!(
+            "expected a {} byte packet, got {} bytes",
+            packet_size, len
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Identifies a game in the recording header. These must never change, as they are stored in
+/// recordings.
+fn game_id(game: Game) -> u8 {
+    match game {
+        Game::Horizon4 => 1,
+        Game::Horizon5 => 2,
+        Game::Motorsport7 => 3,
+        Game::Motorsport2023 => 4,
+    }
+}
+
+fn game_from_id(id: u8) -> Option<Game> {
+    Game::ALL.iter().copied().find(|&game| game_id(game) == id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(game: Game) -> RecordingHeader {
+        RecordingHeader::new(
+            game,
+            UNIX_EPOCH + Duration::from_millis(1_600_000_000_123),
+            "Mugello, wet".to_owned(),
+        )
+    }
+
+    async fn write(header: &RecordingHeader, packets: &[(u64, Vec<u8>)]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut writer = RecordingWriter::create(&mut bytes, header).await.unwrap();
+        for (received, datagram) in packets {
+            writer
+                .write_packet(Duration::from_micros(*received), datagram)
+                .await
+                .unwrap();
+        }
+        writer.flush().await.unwrap();
+        bytes
+    }
+
+    async fn read(bytes: &[u8]) -> io::Result<(RecordingHeader, Vec<(u64, Vec<u8>)>)> {
+        let mut reader = RecordingReader::open(bytes).await?;
+        let mut packets = Vec::new();
+        while let Some(packet) = reader.next_packet().await? {
+            packets.push((packet.received.as_micros() as u64, packet.datagram));
+        }
+        Ok((reader.header().clone(), packets))
+    }
+
+    fn assert_invalid_data<T>(result: io::Result<T>) {
+        match result {
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+            Ok(_) => panic!("expected an InvalidData error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn recordings_round_trip() {
+        let size = Game::Horizon4.datagram_size().unwrap();
+        let packets = vec![
+            (0, vec![1; size]),
+            (16_667, vec![2; size]),
+            (33_333, vec![3; size]),
+        ];
+        let bytes = write(&header(Game::Horizon4), &packets).await;
+        assert!(bytes.starts_with(RECORDING_MAGIC));
+
+        let (read_header, read_packets) = read(&bytes).await.unwrap();
+        assert_eq!(read_header.version, RECORDING_VERSION);
+        assert_eq!(read_header.game, Game::Horizon4);
+        assert_eq!(read_header.packet_size, Some(size));
+        assert_eq!(read_header.start_time, header(Game::Horizon4).start_time);
+        assert_eq!(read_header.notes, "Mugello, wet");
+        assert_eq!(read_packets, packets);
+    }
+
+    #[tokio::test]
+    async fn games_with_several_sizes_round_trip() {
+        let packets = vec![(0, vec![1; 232]), (10, vec![2; 311])];
+        let bytes = write(&header(Game::Motorsport7), &packets).await;
+
+        let (read_header, read_packets) = read(&bytes).await.unwrap();
+        assert_eq!(read_header.game, Game::Motorsport7);
+        assert_eq!(read_header.packet_size, None);
+        assert_eq!(read_packets, packets);
+    }
+
+    #[tokio::test]
+    async fn packets_of_the_wrong_size_are_invalid() {
+        let size = Game::Horizon4.datagram_size().unwrap();
+        let mut bytes = write(&header(Game::Horizon4), &[(0, vec![1; size])]).await;
+        // Another packet that is one byte short.
+        bytes.extend_from_slice(&1u64.to_le_bytes());
+        bytes.extend_from_slice(&(size as u16 - 1).to_le_bytes());
+        bytes.extend(vec![2; size - 1]);
+        assert_invalid_data(read(&bytes).await);
+
+        let mut writer = RecordingWriter::create(Vec::new(), &header(Game::Horizon4))
+            .await
+            .unwrap();
+        assert_invalid_data(writer.write_packet(Duration::default(), &[0; 10]).await);
+    }
+
+    #[tokio::test]
+    async fn truncated_recordings_end_early() {
+        let size = Game::Horizon4.datagram_size().unwrap();
+        let bytes = write(&header(Game::Horizon4), &[(0, vec![1; size])]).await;
+
+        match read(&bytes[..bytes.len() - 1]).await {
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            Ok(_) => panic!("expected an UnexpectedEof error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn other_files_arent_recordings() {
+        assert_invalid_data(RecordingReader::open(&[0u8; 64][..]).await);
+
+        let mut bytes = write(&header(Game::Horizon4), &[]).await;
+        bytes[8] = 2;
+        assert_invalid_data(RecordingReader::open(&bytes[..]).await);
+    }
+}
@@ -0,0 +1,155 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{codec::Datagram, datagram::*, error::DecodeError};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Game {
+    #[serde(rename = "horizon4")]
+    Horizon4,
+    #[serde(rename = "horizon5")]
+    Horizon5,
+    #[serde(rename = "motorsport7")]
+    Motorsport7,
+    #[serde(rename = "motorsport2023")]
+    Motorsport2023,
+}
+
+impl Game {
+    pub const ALL: [Game; 4] = [
+        Game::Horizon4,
+        Game::Horizon5,
+        Game::Motorsport7,
+        Game::Motorsport2023,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Game::Horizon4 => "horizon4",
+            Game::Horizon5 => "horizon5",
+            Game::Motorsport7 => "motorsport7",
+            Game::Motorsport2023 => "motorsport2023",
+        }
+    }
+
+    /// The size of the game's datagrams, or `None` if it sends datagrams of more than one size.
+    pub fn datagram_size(self) -> Option<usize> {
+        match self {
+            Game::Horizon4 => Some(Horizon4Datagram::SIZE),
+            Game::Horizon5 => Some(Horizon5Datagram::SIZE),
+            Game::Motorsport7 => None,
+            Game::Motorsport2023 => Some(Motorsport2023Datagram::SIZE),
+        }
+    }
+}
+
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Game {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Game::ALL
+            .iter()
+            .copied()
+            .find(|game| game.name() == s)
+            .ok_or_else(|| format!("unknown game '{}'", s))
+    }
+}
+
+/// The data shared by every game, normalized from whichever datagram format was received. Fields
+/// that the format doesn't carry are `None`.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Telemetry {
+    pub game: Game,
+    pub sled: Sled,
+    pub dash: Option<Dash>,
+    pub tire_wear: Option<Quad<f32>>,
+    pub track_ordinal: Option<i32>,
+}
+
+impl Telemetry {
+    /// Decodes a datagram from any supported game, telling the formats apart by their size. Forza
+    /// Horizon 4 and 5 share the same format, so both are reported as Forza Horizon 4.
+    pub fn decode(buf: &[u8]) -> Result<Self, DecodeError> {
+        Ok(match buf.len() {
+            Motorsport7SledDatagram::SIZE => Motorsport7SledDatagram::from_bytes(buf)?.into(),
+            Motorsport7DashDatagram::SIZE => Motorsport7DashDatagram::from_bytes(buf)?.into(),
+            Horizon4Datagram::SIZE => Horizon4Datagram::from_bytes(buf)?.into(),
+            Motorsport2023Datagram::SIZE => Motorsport2023Datagram::from_bytes(buf)?.into(),
+            len => return Err(DecodeError::UnknownSize(len)),
+        })
+    }
+
+    /// Decodes a datagram sent by the given game.
+    pub fn decode_game(game: Game, buf: &[u8]) -> Result<Self, DecodeError> {
+        Ok(match game {
+            Game::Horizon4 => Horizon4Datagram::from_bytes(buf)?.into(),
+            Game::Horizon5 => Horizon5Datagram::from_bytes(buf)?.into(),
+            Game::Motorsport7 => match buf.len() {
+                Motorsport7SledDatagram::SIZE => Motorsport7SledDatagram::from_bytes(buf)?.into(),
+                Motorsport7DashDatagram::SIZE => Motorsport7DashDatagram::from_bytes(buf)?.into(),
+                len => return Err(DecodeError::UnknownSize(len)),
+            },
+            Game::Motorsport2023 => Motorsport2023Datagram::from_bytes(buf)?.into(),
+        })
+    }
+
+    fn new(game: Game, sled: Sled, dash: Option<Dash>) -> Self {
+        Self {
+            game,
+            sled,
+            dash,
+            tire_wear: None,
+            track_ordinal: None,
+        }
+    }
+}
+
+impl From<Horizon4Datagram> for Telemetry {
+    fn from(datagram: Horizon4Datagram) -> Self {
+        Telemetry::new(Game::Horizon4, datagram.sled, Some(datagram.dash))
+    }
+}
+
+impl From<Horizon5Datagram> for Telemetry {
+    fn from(datagram: Horizon5Datagram) -> Self {
+        Telemetry::new(Game::Horizon5, datagram.sled, Some(datagram.dash))
+    }
+}
+
+impl From<Motorsport7SledDatagram> for Telemetry {
+    fn from(datagram: Motorsport7SledDatagram) -> Self {
+        Telemetry::new(Game::Motorsport7, datagram.sled, None)
+    }
+}
+
+impl From<Motorsport7DashDatagram> for Telemetry {
+    fn from(datagram: Motorsport7DashDatagram) -> Self {
+        Telemetry::new(Game::Motorsport7, datagram.sled, Some(datagram.dash))
+    }
+}
+
+impl From<Motorsport7Datagram> for Telemetry {
+    fn from(datagram: Motorsport7Datagram) -> Self {
+        match datagram {
+            Motorsport7Datagram::Sled(datagram) => datagram.into(),
+            Motorsport7Datagram::Dash(datagram) => datagram.into(),
+        }
+    }
+}
+
+impl From<Motorsport2023Datagram> for Telemetry {
+    fn from(datagram: Motorsport2023Datagram) -> Self {
+        Self {
+            tire_wear: Some(datagram.tire_wear),
+            track_ordinal: Some(datagram.track_ordinal),
+            ..Telemetry::new(Game::Motorsport2023, datagram.sled, Some(datagram.dash))
+        }
+    }
+}
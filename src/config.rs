@@ -1,13 +1,15 @@
-use std::{collections::HashMap, fmt, ops::RangeInclusive};
+use std::{collections::HashMap, error::Error, fmt, ops::RangeInclusive};
 
 use chroma::MAX_COLUMN;
 use rgb::RGB8;
 use serde::{
-    de::{self, Visitor},
+    de::{self, MapAccess, SeqAccess, Visitor},
     Deserialize, Deserializer,
 };
+use toml::Spanned;
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     #[serde(default)]
     pub colors: HashMap<String, Color>,
@@ -50,19 +52,47 @@ impl<'de> Deserialize<'de> for Color {
 }
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Effect {
     #[serde(default)]
     pub altitude: i32,
-    pub input: Input,
+    /// Effects that read the telemetry themselves, like shift lights, don't have an input.
+    pub input: Option<Input>,
     pub output: Output,
 }
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Input {
+    /// One of the named properties, a telemetry field such as `dash.fuel`, or an expression over
+    /// the fields such as `dash.brake / 255`.
     pub property: String,
     pub max_value: Option<f32>,
     #[serde(default)]
     pub auto_raise: bool,
+    /// Maps this fraction range of the maximum to the whole meter, e.g. `[0.2, 0.8]`.
+    pub remap: Option<[f32; 2]>,
+    /// Readings below this fraction of the maximum count as zero.
+    pub deadzone: Option<f32>,
+    pub curve: Option<Curve>,
+    /// Shows the meter full when the property is at zero, and the other way around.
+    #[serde(default)]
+    pub invert: bool,
+    /// How long in seconds the meter takes to get most of the way to a new reading.
+    pub smoothing: Option<f32>,
+    /// The most the meter can move in a second, as a fraction of the whole meter.
+    pub slew_rate: Option<f32>,
+}
+
+/// A response curve, e.g. `curve = "log"` or `curve = { gamma = 2.2 }`.
+#[derive(Copy, Clone, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Curve {
+    Linear,
+    /// Raises the fraction to this power, so that values above 1 make low readings show less.
+    Gamma(f32),
+    /// Makes low readings show more.
+    Log,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -78,18 +108,41 @@ pub struct Output {
 #[serde(tag = "type")]
 pub enum EffectType {
     #[serde(rename = "meter")]
-    Meter {
-        #[serde(flatten)]
-        config: MeterEffect,
-    },
+    Meter(MeterEffect),
     #[serde(rename = "score")]
-    Score {
-        #[serde(flatten)]
-        config: ScoreEffect,
-    },
+    Score(ScoreEffect),
+    #[serde(rename = "shift-light")]
+    ShiftLight(ShiftLightEffect),
+    #[serde(rename = "gear")]
+    Gear(GearEffect),
+    #[serde(rename = "heatmap")]
+    Heatmap(HeatmapEffect),
+    #[serde(rename = "vector")]
+    Vector(VectorEffect),
+    #[serde(rename = "event")]
+    Event(EventEffect),
+    #[serde(rename = "pedals")]
+    Pedals(PedalsEffect),
+}
+
+impl EffectType {
+    /// Whether the effect reads a property from an `[effect.input]` table.
+    pub fn takes_input(&self) -> bool {
+        match self {
+            EffectType::Meter { .. }
+            | EffectType::Score { .. }
+            | EffectType::Heatmap { .. }
+            | EffectType::Vector { .. }
+            | EffectType::Event { .. } => true,
+            EffectType::ShiftLight { .. } | EffectType::Gear { .. } | EffectType::Pedals { .. } => {
+                false
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct MeterEffect {
     #[serde(default)]
     pub fill: bool,
@@ -97,17 +150,210 @@ pub struct MeterEffect {
 }
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct KeyboardMeter {
     pub column: GridRange,
     pub row: GridRange,
 }
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShiftLightEffect {
+    /// The fraction of the RPM range, from idle to the engine's maximum, where the first key
+    /// lights up.
+    #[serde(default = "default_shift_start")]
+    pub start: f32,
+    /// The fraction of the RPM range where every key is lit and the strip starts flashing.
+    #[serde(default = "default_redline")]
+    pub redline: f32,
+    /// The color the strip flashes in at the redline. Defaults to the effect's color.
+    pub redline_color: Option<String>,
+    /// How many times a second the strip flashes at the redline.
+    #[serde(default = "default_flash_rate")]
+    pub flash_rate: f32,
+    /// Shift points for particular cars or classes of car, which take precedence in that order.
+    #[serde(default)]
+    pub car: Vec<CarShiftPoints>,
+    pub keyboard: Option<KeyboardMeter>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CarShiftPoints {
+    /// The car's `car_ordinal`.
+    pub ordinal: Option<i32>,
+    /// The car's `car_class`, from 0 for D up to 6 for X.
+    pub class: Option<i32>,
+    pub start: Option<f32>,
+    pub redline: Option<f32>,
+}
+
+fn default_shift_start() -> f32 {
+    0.75
+}
+
+fn default_redline() -> f32 {
+    0.95
+}
+
+fn default_flash_rate() -> f32 {
+    8.0
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GearEffect {
+    /// How long every number key flashes for after a gear change, in seconds. Gear changes aren't
+    /// flashed without it.
+    pub flash: Option<f32>,
+    /// The color of the flash. Defaults to the effect's color.
+    pub flash_color: Option<String>,
+    pub keyboard: Option<KeyboardScore>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeatmapEffect {
+    /// Values at or below this are shown in `cold_color`.
+    pub cold: f32,
+    /// Values here are shown in the effect's color, which blends into the cold and hot colors on
+    /// either side.
+    pub optimal: f32,
+    /// Values at or above this are shown in `hot_color`.
+    pub hot: f32,
+    pub cold_color: String,
+    pub hot_color: String,
+    pub keyboard: Option<KeyboardQuad>,
+}
+
+/// Where each wheel is shown. Each defaults to a quarter of the keyboard, e.g. the top left for the
+/// front left wheel.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyboardQuad {
+    pub front_left: Option<KeyboardRegion>,
+    pub front_right: Option<KeyboardRegion>,
+    pub rear_left: Option<KeyboardRegion>,
+    pub rear_right: Option<KeyboardRegion>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyboardRegion {
+    pub column: GridRange,
+    pub row: GridRange,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VectorEffect {
+    /// How far from the dot keys are lit, fading out with the distance. Without it only the key
+    /// nearest to the dot is lit.
+    #[serde(default)]
+    pub radius: f32,
+    pub keyboard: Option<KeyboardRegion>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EventEffect {
+    /// A fraction of the property's maximum, which `trigger` compares the property to.
+    #[serde(default = "default_threshold")]
+    pub threshold: f32,
+    #[serde(default)]
+    pub trigger: Trigger,
+    #[serde(default)]
+    pub animation: Animation,
+    /// How long the animation runs for, in seconds.
+    #[serde(default = "default_duration")]
+    pub duration: f32,
+    /// The keys that the animation is shown on. Defaults to the whole keyboard.
+    pub keyboard: Option<KeyboardRegion>,
+}
+
+/// When an event effect goes off.
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+pub enum Trigger {
+    /// When the property goes from below the threshold to at or above it, e.g. when a wheel
+    /// touches a rumble strip.
+    #[default]
+    #[serde(rename = "rise")]
+    Rise,
+    /// When the property goes up by at least the threshold from one datagram to the next, e.g.
+    /// when the car hits something.
+    #[serde(rename = "jump")]
+    Jump,
+}
+
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+pub enum Animation {
+    /// Every key is lit until the animation ends.
+    #[default]
+    #[serde(rename = "flash")]
+    Flash,
+    /// Every key is lit and fades out.
+    #[serde(rename = "pulse")]
+    Pulse,
+    /// A ring that spreads out from the middle of the keys and fades out.
+    #[serde(rename = "ripple")]
+    Ripple,
+}
+
+fn default_threshold() -> f32 {
+    0.5
+}
+
+fn default_duration() -> f32 {
+    0.3
+}
+
+/// Shows the pedals as bars, each in its own colors. The effect's color isn't used.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PedalsEffect {
+    /// The rows that every bar fills up along as its pedal is pressed.
+    #[serde(default = "default_pedal_row")]
+    pub row: GridRange,
+    /// The pedals to show. Without it all four are shown.
+    pub pedals: Option<Pedals>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pedals {
+    pub accel: Option<Pedal>,
+    pub brake: Option<Pedal>,
+    pub clutch: Option<Pedal>,
+    pub hand_brake: Option<Pedal>,
+}
+
+/// Where and in what color a pedal is shown.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pedal {
+    /// Defaults to 21 for the throttle, 20 for the brake, 19 for the clutch and 18 for the hand
+    /// brake, which are the numpad's columns.
+    pub column: Option<GridRange>,
+    /// A color from `[colors]`. Defaults to green (0, 255, 0) for the throttle, red (255, 0, 0)
+    /// for the brake, blue (0, 128, 255) for the clutch and yellow (255, 200, 0) for the hand
+    /// brake.
+    pub color: Option<String>,
+}
+
+fn default_pedal_row() -> GridRange {
+    // From the bottom of the numpad to its top, i.e. "5->1".
+    let (bottom, top) = (5, 1);
+    GridRange::Direction(bottom..=top)
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ScoreEffect {
     pub keyboard: Option<KeyboardScore>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct KeyboardScore {
     pub numkeys: NumKeys,
 }
@@ -194,13 +440,13 @@ impl<'de> Deserialize<'de> for GridRange {
                         }
                         Some(_) => return None,
                         None => {
-                            let v = u8::from_str_radix(&v, 10).ok()?;
+                            let v = v.parse::<u8>().ok()?;
                             return Some(GridRange::Range(v..=v));
                         }
                     };
 
-                    let left = u8::from_str_radix(&v[..i], 10).ok()?;
-                    let right = u8::from_str_radix(&v[j..], 10).ok()?;
+                    let left = v[..i].parse::<u8>().ok()?;
+                    let right = v[j..].parse::<u8>().ok()?;
 
                     Some(match separator {
                         Separator::Colon => GridRange::Range(if left < right {
@@ -231,3 +477,417 @@ impl<'de> Deserialize<'de> for GridRange {
 fn white() -> String {
     "white".to_string()
 }
+
+/// Looks up a color defined in the `[colors]` table.
+pub fn color(colors: &HashMap<String, Color>, name: &str) -> Result<RGB8, Problem> {
+    colors.get(name).map(|color| color.0).ok_or_else(|| {
+        Problem::new(
+            "output.color",
+            format!(
+                "unknown color '{}' - colors must be defined in [colors]",
+                name
+            ),
+        )
+    })
+}
+
+/// Something wrong with a value in an effect's config.
+#[derive(Clone, Debug)]
+pub struct Problem {
+    /// The dotted path to the value within the effect, e.g. `output.keyboard.row`.
+    pub key: &'static str,
+    pub message: String,
+}
+
+impl Problem {
+    pub fn new(key: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            key,
+            message: message.into(),
+        }
+    }
+}
+
+/// A problem with one of the effects in a config.
+#[derive(Clone, Debug)]
+pub struct ConfigError {
+    /// The index of the effect in the config.
+    pub effect: usize,
+    pub problem: Problem,
+}
+
+/// Every problem found in a config file, shown along with where it is in the file.
+#[derive(Debug)]
+pub struct InvalidConfig {
+    path: String,
+    source: String,
+    errors: Vec<ConfigError>,
+}
+
+impl InvalidConfig {
+    pub fn new(path: &str, source: &str, errors: Vec<ConfigError>) -> Self {
+        Self {
+            path: path.to_owned(),
+            source: source.to_owned(),
+            errors,
+        }
+    }
+}
+
+impl fmt::Display for InvalidConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, error) in self.errors.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            match locate(&self.source, error.effect, error.problem.key) {
+                Some(line) => write!(f, "{}:{}: ", self.path, line)?,
+                None => write!(f, "{}: ", self.path)?,
+            }
+            write!(
+                f,
+                "effect #{}: {}: {}",
+                error.effect + 1,
+                error.problem.key,
+                error.problem.message
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for InvalidConfig {}
+
+/// Describes a config that couldn't be parsed. toml reports unknown fields at the end of their
+/// table, so the line of the field itself is looked up instead.
+pub fn parse_error(path: &str, source: &str, error: &toml::de::Error) -> String {
+    let message = error.to_string();
+    match locate_unknown_field(source, &message) {
+        Some(line) => {
+            let message = match message.rfind(" at line ") {
+                Some(end) => &message[..end],
+                None => &message[..],
+            };
+            format!("{}:{}: {}", path, line, message)
+        }
+        None => format!("{}: {}", path, message),
+    }
+}
+
+/// Finds the line of the field in an "unknown field `name` ... for key `table`" message.
+fn locate_unknown_field(source: &str, message: &str) -> Option<usize> {
+    let quoted = |prefix: &str| {
+        let start = message.find(prefix)? + prefix.len();
+        let end = start + message[start..].find('`')?;
+        Some(&message[start..end])
+    };
+    let field = quoted("unknown field `")?;
+    // Fields at the top level aren't in a table.
+    let table: Vec<_> = quoted("for key `").map_or(vec![], |table| table.split('.').collect());
+
+    // The path stops short at flattened tables, so failing that it's looked for further in.
+    let root: TomlNode = toml::from_str(source).ok()?;
+    let offset = root
+        .find(&table, field, false)
+        .or_else(|| root.find(&table, field, true))?;
+    Some(source[..offset].matches('\n').count() + 1)
+}
+
+/// Finds the line a value in an effect is defined on, or the line of the deepest table that holds
+/// it if the value is missing.
+fn locate(source: &str, effect: usize, key: &str) -> Option<usize> {
+    let root: TomlNode = toml::from_str(source).ok()?;
+    let effect = match root.entry("effect")? {
+        (_, TomlNode::Array(effects)) => effects.get(effect)?,
+        _ => return None,
+    };
+
+    // Array elements have no span of their own, so an effect is found by its first key.
+    let mut offset = effect.first_key()?;
+    let mut node = effect;
+    for name in key.split('.') {
+        match node.entry(name) {
+            Some((key, value)) => {
+                offset = key.start();
+                node = value;
+            }
+            None => break,
+        }
+    }
+
+    Some(source[..offset].matches('\n').count() + 1)
+}
+
+/// The shape of a TOML document along with where each key is, however it's written: in a table
+/// header, as a dotted key or in an inline table.
+enum TomlNode {
+    Table(Vec<(Spanned<String>, TomlNode)>),
+    Array(Vec<TomlNode>),
+    Value,
+}
+
+impl TomlNode {
+    fn entry(&self, name: &str) -> Option<&(Spanned<String>, TomlNode)> {
+        match self {
+            TomlNode::Table(entries) => entries.iter().find(|(key, _)| key.get_ref() == name),
+            _ => None,
+        }
+    }
+
+    /// The offset of `field` in the table at `path`, or with `nested` in a table within it. The path
+    /// doesn't say which element of an array it goes through, so the first one that has the field
+    /// is taken.
+    fn find(&self, path: &[&str], field: &str, nested: bool) -> Option<usize> {
+        match (self, path) {
+            (TomlNode::Array(elements), _) => elements
+                .iter()
+                .find_map(|element| element.find(path, field, nested)),
+            (TomlNode::Table(entries), []) => match self.entry(field) {
+                Some((key, _)) => Some(key.start()),
+                None if nested => entries
+                    .iter()
+                    .find_map(|(_, value)| value.find(&[], field, nested)),
+                None => None,
+            },
+            (TomlNode::Table(_), [name, rest @ ..]) => {
+                self.entry(name)?.1.find(rest, field, nested)
+            }
+            (TomlNode::Value, _) => None,
+        }
+    }
+
+    /// The offset of the first key in a table.
+    fn first_key(&self) -> Option<usize> {
+        match self {
+            TomlNode::Table(entries) => entries.iter().map(|(key, _)| key.start()).min(),
+            _ => None,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TomlNode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct TomlNodeVisitor;
+
+        impl<'de> Visitor<'de> for TomlNodeVisitor {
+            type Value = TomlNode;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("any TOML value")
+            }
+
+            fn visit_bool<E: de::Error>(self, _: bool) -> Result<Self::Value, E> {
+                Ok(TomlNode::Value)
+            }
+
+            fn visit_i64<E: de::Error>(self, _: i64) -> Result<Self::Value, E> {
+                Ok(TomlNode::Value)
+            }
+
+            fn visit_u64<E: de::Error>(self, _: u64) -> Result<Self::Value, E> {
+                Ok(TomlNode::Value)
+            }
+
+            fn visit_f64<E: de::Error>(self, _: f64) -> Result<Self::Value, E> {
+                Ok(TomlNode::Value)
+            }
+
+            fn visit_str<E: de::Error>(self, _: &str) -> Result<Self::Value, E> {
+                Ok(TomlNode::Value)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut elements = Vec::new();
+                while let Some(element) = seq.next_element()? {
+                    elements.push(element);
+                }
+                Ok(TomlNode::Array(elements))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(TomlNode::Table(entries))
+            }
+        }
+
+        deserializer.deserialize_any(TomlNodeVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locate_in(source: &str, effect: usize, key: &str) -> Option<usize> {
+        locate(&source.replace("\n            ", "\n"), effect, key)
+    }
+
+    #[test]
+    fn values_are_located_in_tables() {
+        let source = r#"[colors]
+            white = "ffffff"
+
+            [[effect]]
+            [effect.input]
+            property = "rpm"
+            [effect.output]
+            type = "meter"
+            color = "white"
+            [effect.output.keyboard]
+            row = 1
+
+            [[effect]]
+            [effect.input]
+            property = "speed"
+        "#;
+
+        assert_eq!(locate_in(source, 0, "input.property"), Some(6));
+        assert_eq!(locate_in(source, 0, "output.color"), Some(9));
+        assert_eq!(locate_in(source, 0, "output.keyboard.row"), Some(11));
+        assert_eq!(locate_in(source, 0, "output.keyboard"), Some(10));
+        assert_eq!(locate_in(source, 1, "input.property"), Some(15));
+        assert_eq!(locate_in(source, 2, "input.property"), None);
+    }
+
+    #[test]
+    fn missing_values_are_located_at_their_table() {
+        let source = r#"[[effect]]
+            [effect.input]
+            property = "rpm"
+            [effect.output]
+            type = "meter"
+
+            [[effect]]
+            # Nothing but a comment.
+            input.property = "speed"
+        "#;
+
+        assert_eq!(locate_in(source, 0, "output.keyboard.row"), Some(4));
+        assert_eq!(locate_in(source, 0, "input.curve"), Some(2));
+        assert_eq!(locate_in(source, 1, "output.color"), Some(9));
+    }
+
+    #[test]
+    fn values_are_located_in_inline_tables_dotted_keys_and_multi_line_values() {
+        let source = r#"[[effect]]
+            input = { property = "rpm", filter = { smoothing = 0.5 } }
+            output.type = "meter"
+            output.keyboard.row = 1
+            output.keyboard.column = [
+                "0->9",
+            ]
+            output.color = """
+            white"""
+        "#;
+
+        assert_eq!(locate_in(source, 0, "input.property"), Some(2));
+        assert_eq!(locate_in(source, 0, "input.filter.smoothing"), Some(2));
+        assert_eq!(locate_in(source, 0, "input.curve"), Some(2));
+        assert_eq!(locate_in(source, 0, "output.type"), Some(3));
+        assert_eq!(locate_in(source, 0, "output.keyboard.column"), Some(5));
+        assert_eq!(locate_in(source, 0, "output.color"), Some(8));
+        assert_eq!(locate_in(source, 0, "output.fill"), Some(3));
+    }
+
+    #[test]
+    fn effects_can_be_an_array_of_inline_tables() {
+        let source = r#"effect = [
+                { input = { property = "rpm" } },
+                { input = { property = "speed" } },
+            ]
+        "#;
+
+        assert_eq!(locate_in(source, 1, "input.property"), Some(3));
+        assert_eq!(locate_in(source, 1, "output.color"), Some(3));
+    }
+
+    fn parse_error_in(source: &str) -> String {
+        let source = source.replace("\n            ", "\n");
+        let error = toml::from_str::<Config>(&source).unwrap_err();
+        parse_error("c.toml", &source, &error)
+    }
+
+    #[test]
+    fn unknown_fields_are_located() {
+        let source = r#"[colors]
+            white = "ffffff"
+
+            [[effect]]
+            [effect.input]
+            property = "rpm"
+            [effect.output]
+            type = "meter"
+            fill = true
+            [effect.output.keyboard]
+            column = "0->9"
+            row = 1
+
+            [[effect]]
+            [effect.input]
+            property = "speed"
+            [effect.output]
+            type = "meter"
+            fill = true
+            [effect.output.keyboard]
+            column = "0->9"
+            row = 2
+        "#;
+
+        assert_eq!(
+            parse_error_in(&source.replace("property = \"rpm\"", "propery = \"rpm\"")),
+            "c.toml:6: unknown field `propery`, expected one of `property`, `max_value`, \
+             `auto_raise`, `remap`, `deadzone`, `curve`, `invert`, `smoothing`, `slew_rate` for key \
+             `effect.input`"
+        );
+        // In the second effect, and in a flattened table.
+        assert_eq!(
+            parse_error_in(&source.replacen("fill = true", "fil = true", 2).replacen(
+                "fil = true",
+                "fill = true",
+                1
+            )),
+            "c.toml:19: unknown field `fil`, expected `fill` or `keyboard` for key `effect.output`"
+        );
+        // In a table within a flattened table.
+        assert_eq!(
+            parse_error_in(&source.replace("row = 2", "rows = 2")),
+            "c.toml:22: unknown field `rows`, expected `column` or `row` for key `effect.output`"
+        );
+        assert_eq!(
+            parse_error_in(&format!("altitud = 1\n{}", source)),
+            "c.toml:1: unknown field `altitud`, expected `colors` or `effect`"
+        );
+    }
+
+    #[test]
+    fn unknown_fields_are_located_in_inline_tables() {
+        assert_eq!(
+            parse_error_in(
+                r#"[[effect]]
+                output = { type = "meter" }
+                input = { property = "rpm", smoothin = 0.5 }
+                "#
+            ),
+            "c.toml:3: unknown field `smoothin`, expected one of `property`, `max_value`, \
+             `auto_raise`, `remap`, `deadzone`, `curve`, `invert`, `smoothing`, `slew_rate` for key \
+             `effect.input`"
+        );
+    }
+
+    #[test]
+    fn other_parse_errors_keep_tomls_location() {
+        assert_eq!(
+            parse_error_in("[[effect]]\naltitude = \"high\"\n"),
+            "c.toml: invalid type: string \"high\", expected i32 for key `effect.altitude` at line \
+             2 column 12"
+        );
+    }
+}
@@ -1,68 +1,235 @@
-use std::{future::Future, time::Instant};
+use std::{error::Error, future::Future, rc::Rc, sync::Arc, time::Instant};
 
+use chroma::LightingBackend;
+use futures::future;
 use tokio::stream::{Stream, StreamExt};
 
 use crate::{
-    config::{Config, EffectType},
-    effects::{Effect, EffectImpl, MeterEffect, PositionEffect},
+    config::{self, Config, ConfigError, EffectType, InvalidConfig, Problem},
+    effects::{
+        Effect, EffectImpl, EffectInstance, EventEffect, GearEffect, HeatmapEffect, MeterEffect,
+        PedalsEffect, PositionEffect, ShiftLightEffect, VectorEffect,
+    },
+    learned::LearnedMaxima,
     property::{self, Property},
     state::{ChromaState, Tick},
+    watch::ConfigWatcher,
 };
 
 pub struct Driver {
     effects: Vec<Effect>,
+    learned: Rc<LearnedMaxima>,
 }
 
 impl Driver {
-    pub fn from_config(config: &Config) -> Self {
-        let mut driver = Self { effects: vec![] };
-
-        for effect in &config.effect {
-            let property = property::query_property(&effect.input);
-
-            let implementation: Box<dyn EffectImpl> = match &effect.output.effect_type {
-                EffectType::Meter {
-                    config: meter_config,
-                } => {
-                    let rate_property = match property {
-                        Property::Rate(r) => r,
-                        _ => panic!(
-                            "Meter effect is not compatible with property '{}'",
-                            effect.input.property
-                        ),
-                    };
+    /// Reads and validates the config at `path`. Every problem with the config is reported at
+    /// once.
+    pub async fn load(path: &str, learned: &Rc<LearnedMaxima>) -> Result<Self, Box<dyn Error>> {
+        let source = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| format!("{}: {}", path, e))?;
+        let config: Config =
+            toml::from_str(&source).map_err(|e| config::parse_error(path, &source, &e))?;
 
-                    Box::new(MeterEffect::new(
-                        rate_property,
-                        &effect.output,
-                        meter_config,
-                        &config.colors,
-                    ))
-                }
-                EffectType::Score {
-                    config: score_config,
-                } => {
-                    let score_property = match property {
-                        Property::Score(p) => p,
-                        _ => panic!(
-                            "Score effect is not compatible with property '{}'",
-                            effect.input.property
-                        ),
-                    };
+        Ok(Self::from_config(&config, learned)
+            .map_err(|errors| InvalidConfig::new(path, &source, errors))?)
+    }
 
-                    Box::new(PositionEffect::new(
-                        score_property,
-                        &effect.output,
-                        score_config,
-                        &config.colors,
-                    ))
+    /// Builds the effects in `config`. Properties that `auto_raise` start from the maxima in
+    /// `learned`, and add to them.
+    pub fn from_config(
+        config: &Config,
+        learned: &Rc<LearnedMaxima>,
+    ) -> Result<Self, Vec<ConfigError>> {
+        let mut driver = Self {
+            effects: vec![],
+            learned: learned.clone(),
+        };
+        let mut errors = vec![];
+
+        for (i, effect) in config.effect.iter().enumerate() {
+            match Self::effect_from_config(config, effect, learned) {
+                Ok(implementation) => {
+                    driver.add_effect(Effect::new(effect.altitude, implementation))
                 }
-            };
+                Err(problems) => errors.extend(
+                    problems
+                        .into_iter()
+                        .map(|problem| ConfigError { effect: i, problem }),
+                ),
+            }
+        }
 
-            driver.add_effect(Effect::new(effect.altitude, implementation));
+        if errors.is_empty() {
+            Ok(driver)
+        } else {
+            Err(errors)
         }
+    }
+
+    fn effect_from_config(
+        config: &Config,
+        effect: &config::Effect,
+        learned: &Rc<LearnedMaxima>,
+    ) -> Result<Box<dyn EffectImpl>, Vec<Problem>> {
+        let effect_type = &effect.output.effect_type;
+        let mut problems = vec![];
+
+        let input = match (&effect.input, effect_type.takes_input()) {
+            (Some(input), true) => Some(input),
+            (Some(_), false) => {
+                problems.push(Problem::new(
+                    "input",
+                    "this effect reads the telemetry itself and doesn't take an [effect.input] \
+                     table",
+                ));
+                None
+            }
+            (None, true) => {
+                problems.push(Problem::new(
+                    "input",
+                    "this effect needs an [effect.input] table",
+                ));
+                None
+            }
+            (None, false) => None,
+        };
+
+        // Without a property, the output is still checked so that every problem can be fixed in
+        // one go.
+        let property = match input.map(|input| property::query_property(input, learned)) {
+            Some(Ok(property)) => Some(property),
+            Some(Err(more)) => {
+                problems.extend(more);
+                None
+            }
+            None => None,
+        };
+        let property_name = input.map_or("", |input| &input.property[..]);
+        let incompatible = |kind: &str| {
+            Problem::new(
+                "input.property",
+                format!(
+                    "{} effects aren't compatible with property '{}'",
+                    kind, property_name
+                ),
+            )
+        };
+        let no_max = || {
+            Problem::new(
+                "input.max_value",
+                format!(
+                    "property '{}' has no maximum - set max_value or auto_raise",
+                    property_name
+                ),
+            )
+        };
+
+        let implementation: Result<Box<dyn EffectImpl>, Vec<Problem>> = match effect_type {
+            EffectType::Meter(meter_config) => {
+                let rate_property = match property {
+                    Some(Property::Rate(r)) => Some(r),
+                    Some(_) => {
+                        problems.push(incompatible("meter"));
+                        None
+                    }
+                    None => None,
+                };
+
+                if rate_property.as_ref().is_some_and(|r| !r.has_max()) {
+                    problems.push(no_max());
+                }
+
+                MeterEffect::new(rate_property, &effect.output, meter_config, &config.colors)
+                    .map(|meter| Box::new(meter) as _)
+            }
+            EffectType::Score(score_config) => {
+                let score_property = match property {
+                    Some(Property::Score(p)) => Some(p),
+                    Some(_) => {
+                        problems.push(incompatible("score"));
+                        None
+                    }
+                    None => None,
+                };
+
+                PositionEffect::new(score_property, &effect.output, score_config, &config.colors)
+                    .map(|position| Box::new(position) as _)
+            }
+            EffectType::ShiftLight(shift_light_config) => {
+                ShiftLightEffect::new(&effect.output, shift_light_config, &config.colors)
+                    .map(|shift_light| Box::new(shift_light) as _)
+            }
+            EffectType::Gear(gear_config) => {
+                GearEffect::new(&effect.output, gear_config, &config.colors)
+                    .map(|gear| Box::new(gear) as _)
+            }
+            EffectType::Heatmap(heatmap_config) => {
+                let quad_property = match property {
+                    Some(Property::Quad(p)) => Some(p),
+                    Some(_) => {
+                        problems.push(incompatible("heatmap"));
+                        None
+                    }
+                    None => None,
+                };
+
+                HeatmapEffect::new(
+                    quad_property,
+                    &effect.output,
+                    heatmap_config,
+                    &config.colors,
+                )
+                .map(|heatmap| Box::new(heatmap) as _)
+            }
+            EffectType::Vector(vector_config) => {
+                let vector_property = match property {
+                    Some(Property::Vector(p)) => Some(p),
+                    Some(_) => {
+                        problems.push(incompatible("vector"));
+                        None
+                    }
+                    None => None,
+                };
+
+                VectorEffect::new(
+                    vector_property,
+                    &effect.output,
+                    vector_config,
+                    &config.colors,
+                )
+                .map(|vector| Box::new(vector) as _)
+            }
+            EffectType::Event(event_config) => {
+                let rate_property = match property {
+                    Some(Property::Rate(r)) => Some(r),
+                    Some(_) => {
+                        problems.push(incompatible("event"));
+                        None
+                    }
+                    None => None,
+                };
+
+                if rate_property.as_ref().is_some_and(|r| !r.has_max()) {
+                    problems.push(no_max());
+                }
 
-        driver
+                EventEffect::new(rate_property, &effect.output, event_config, &config.colors)
+                    .map(|event| Box::new(event) as _)
+            }
+            EffectType::Pedals(pedals_config) => {
+                PedalsEffect::new(pedals_config, &config.colors).map(|pedals| Box::new(pedals) as _)
+            }
+        };
+
+        match implementation {
+            Ok(implementation) if problems.is_empty() => Ok(implementation),
+            Ok(_) => Err(problems),
+            Err(more) => {
+                problems.extend(more);
+                Err(problems)
+            }
+        }
     }
 
     fn add_effect(&mut self, effect: Effect) {
@@ -76,10 +243,9 @@ impl Driver {
                     .iter()
                     .skip(i)
                     .enumerate()
-                    .skip_while(|(_, e)| effect.altitude() == e.altitude())
-                    .next();
+                    .find(|(_, e)| effect.altitude() != e.altitude());
                 match effect {
-                    Some((i, _)) => i,
+                    Some((j, _)) => i + j,
                     None => self.effects.len(),
                 }
             }
@@ -89,35 +255,407 @@ impl Driver {
         self.effects.insert(insert_at, effect);
     }
 
+    pub fn start(&self) -> Session<'_> {
+        Session {
+            instances: self.effects.iter().map(|e| e.start()).collect(),
+            last: None,
+        }
+    }
+
+    /// Runs the effects until the stream ends or `cancel` completes. With a `watcher`, the config
+    /// is reloaded whenever it changes and the new effects take over from the next datagram on. A
+    /// config that fails to load is reported and the current effects are kept.
+    ///
+    /// The learned maxima are saved whenever a race ends, and once more when the run is over.
     pub async fn run(
-        &self,
-        mut stream: impl Stream<Item = std::io::Result<forza::Horizon4Datagram>> + Unpin,
+        self,
+        backend: &Arc<dyn LightingBackend>,
+        stream: impl Stream<Item = forza::Result<forza::Telemetry>> + Unpin,
+        cancel: impl Future<Output = ()> + Unpin,
+        watcher: Option<ConfigWatcher>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let learned = self.learned.clone();
+        let result = self.run_effects(backend, stream, cancel, watcher).await;
+        save_learned(&learned);
+        result
+    }
+
+    async fn run_effects(
+        mut self,
+        backend: &Arc<dyn LightingBackend>,
+        mut stream: impl Stream<Item = forza::Result<forza::Telemetry>> + Unpin,
         mut cancel: impl Future<Output = ()> + Unpin,
+        mut watcher: Option<ConfigWatcher>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut instances: Vec<_> = self.effects.iter().map(|e| e.start()).collect();
-
-        let mut last = None;
-        while let Some(Ok(datagram)) = tokio::select! {
-            datagram = stream.next() => datagram,
-            _ = &mut cancel => None
-        } {
-            let mut state = ChromaState::new();
-
-            let now = Instant::now();
-            let tick = Tick {
-                now,
-                // elapsed: last.map(|last| now.duration_since(last)),
-                elapsed: last.map(|last| now - last),
+        // The datagram that the reloaded effects start with.
+        let mut carried = None;
+        let mut race_on = false;
+
+        loop {
+            let (driver, telemetry) = {
+                let mut session = self.start();
+                if let Some(telemetry) = carried.take() {
+                    session.step(&telemetry, Instant::now()).apply(backend)?;
+                }
+
+                let mut reloaded = None;
+                loop {
+                    let telemetry = tokio::select! {
+                        telemetry = stream.next() => telemetry,
+                        _ = &mut cancel => None,
+                        path = changed(&mut watcher) => {
+                            match Self::load(path, &self.learned).await {
+                                Ok(driver) => {
+                                    eprintln!("Reloaded {}", path);
+                                    reloaded = Some(driver);
+                                }
+                                Err(e) => eprintln!("Warning: keeping the previous config: {}", e),
+                            }
+                            continue;
+                        }
+                    };
+
+                    let telemetry = match telemetry {
+                        Some(Ok(telemetry)) => telemetry,
+                        // A malformed datagram shouldn't end the session.
+                        Some(Err(forza::Error::Decode(e))) => {
+                            eprintln!("Warning: skipping datagram: {}", e);
+                            continue;
+                        }
+                        // Neither should a relay target that went away.
+                        Some(Err(e @ forza::Error::Relay(..))) => {
+                            eprintln!("Warning: {}", e);
+                            continue;
+                        }
+                        Some(Err(e)) => return Err(e.into()),
+                        None => return Ok(()),
+                    };
+
+                    let was_race_on = race_on;
+                    race_on = telemetry.sled.is_race_on != 0;
+                    if was_race_on && !race_on {
+                        save_learned(&self.learned);
+                    }
+
+                    match reloaded.take() {
+                        Some(driver) => break (driver, telemetry),
+                        None => session.step(&telemetry, Instant::now()).apply(backend)?,
+                    }
+                }
             };
-            for i in &mut instances {
-                i.update(&datagram);
-                i.tick(&tick, &mut state);
-            }
-            last = Some(now);
 
-            state.apply()?;
+            self = driver;
+            carried = Some(telemetry);
+        }
+    }
+}
+
+fn save_learned(learned: &LearnedMaxima) {
+    if let Err(e) = learned.save() {
+        eprintln!("Warning: couldn't save the learned maxima: {}", e);
+    }
+}
+
+/// Waits for the watched config to change, returning its path. Never completes without a watcher.
+async fn changed(watcher: &mut Option<ConfigWatcher>) -> &str {
+    match watcher {
+        Some(watcher) => {
+            watcher.changed().await;
+            watcher.path()
+        }
+        None => future::pending().await,
+    }
+}
+
+/// The running instances of a driver's effects.
+pub struct Session<'a> {
+    instances: Vec<Box<dyn 'a + EffectInstance>>,
+    last: Option<Instant>,
+}
+
+impl<'a> Session<'a> {
+    /// Feeds telemetry to every effect and returns what the keyboard should show at `now`.
+    pub fn step(&mut self, telemetry: &forza::Telemetry, now: Instant) -> ChromaState {
+        let mut state = ChromaState::new();
+
+        let tick = Tick {
+            now,
+            elapsed: self.last.map(|last| now - last),
+        };
+        for i in &mut self.instances {
+            i.update(telemetry);
+            i.tick(&tick, &mut state);
+        }
+        self.last = Some(now);
+
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use chroma::VirtualKeyboard;
+    use forza::DecodeError;
+
+    use super::*;
+    use crate::testing::{self, BLACK, WHITE};
+
+    /// Records when it's started, so that the order of a driver's effects can be checked.
+    struct Marker {
+        id: u32,
+        started: Rc<RefCell<Vec<u32>>>,
+    }
+
+    impl EffectImpl for Marker {
+        fn start<'a>(&'a self) -> Box<dyn 'a + EffectInstance> {
+            self.started.borrow_mut().push(self.id);
+            Box::new(Idle)
+        }
+    }
+
+    struct Idle;
+
+    impl EffectInstance for Idle {
+        fn update(&mut self, _telemetry: &forza::Telemetry) {}
+
+        fn tick(&mut self, _tick: &Tick, _state: &mut ChromaState) {}
+    }
+
+    #[test]
+    fn effects_are_ordered_by_altitude_then_as_added() {
+        let started = Rc::new(RefCell::new(vec![]));
+        let mut driver = Driver {
+            effects: vec![],
+            learned: testing::learned(),
+        };
+
+        for (id, altitude) in [(1, -1), (2, 0), (3, 1), (4, 0), (5, 0), (6, -1)] {
+            let marker = Marker {
+                id,
+                started: started.clone(),
+            };
+            driver.add_effect(Effect::new(altitude, Box::new(marker)));
+        }
+        driver.start();
+
+        assert_eq!(*started.borrow(), [1, 6, 2, 4, 5, 3]);
+    }
+
+    #[test]
+    fn every_problem_with_an_effect_is_reported() {
+        let keys = |config| {
+            testing::problems(config)
+                .into_iter()
+                .map(|problem| problem.key)
+                .collect::<Vec<_>>()
+        };
+
+        // An incompatible property, and then the output.
+        assert_eq!(
+            keys(
+                r#"
+                [[effect]]
+                [effect.input]
+                property = "position"
+                [effect.output]
+                type = "meter"
+                color = "purple"
+                "#
+            ),
+            ["input.property", "output.color", "output.keyboard"]
+        );
+        assert_eq!(
+            keys(
+                r#"
+                [[effect]]
+                [effect.input]
+                property = "rpm"
+                [effect.output]
+                type = "heatmap"
+                color = "purple"
+                cold = 3
+                optimal = 2
+                hot = 1
+                cold_color = "white"
+                hot_color = "white"
+                "#
+            ),
+            ["input.property", "output.color", "output.optimal"]
+        );
+        // A missing or unknown property, and then the output.
+        assert_eq!(
+            keys(
+                r#"
+                [[effect]]
+                [effect.output]
+                type = "vector"
+                color = "purple"
+                "#
+            ),
+            ["input", "output.color", "output.keyboard"]
+        );
+        assert_eq!(
+            keys(
+                r#"
+                [[effect]]
+                [effect.input]
+                property = "nonsense"
+                [effect.output]
+                type = "event"
+                color = "purple"
+                duration = 0
+                "#
+            ),
+            ["input.property", "output.color", "output.duration"]
+        );
+    }
+
+    const METER: &str = r#"
+        [[effect]]
+        [effect.input]
+        property = "rpm-baseline"
+        [effect.output]
+        type = "meter"
+        color = "white"
+        fill = true
+        [effect.output.keyboard]
+        column = "0->9"
+        row = 1
+    "#;
+
+    #[tokio::test]
+    async fn run_shows_every_datagram() {
+        let keyboard = Arc::new(VirtualKeyboard::new());
+        let backend: Arc<dyn LightingBackend> = keyboard.clone();
+
+        let mut race_off = testing::telemetry(8000.0);
+        race_off.sled.is_race_on = 0;
+        let stream = tokio::stream::iter(vec![
+            Ok(testing::telemetry(1000.0)),
+            Ok(testing::telemetry(4500.0)),
+            // Skipped, rather than ending the run.
+            Err(forza::Error::Decode(DecodeError::UnknownSize(3))),
+            Ok(testing::telemetry(8000.0)),
+            Ok(race_off),
+        ]);
+
+        testing::driver(METER)
+            .run(&backend, stream, future::pending(), None)
+            .await
+            .unwrap();
+
+        let frames = keyboard.frames();
+        let meters: Vec<_> = frames
+            .iter()
+            .map(|frame| testing::row(frame, 1, 0..10))
+            .collect();
+        let lit = |keys: usize| {
+            let mut meter = vec![BLACK; 10];
+            meter[..keys].fill(WHITE);
+            meter
+        };
+        assert_eq!(meters, [lit(0), lit(5), lit(10), lit(0)]);
+    }
+
+    #[tokio::test]
+    async fn bundled_configs_are_valid() {
+        let configs = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("configs");
+        for entry in std::fs::read_dir(configs).unwrap() {
+            let path = entry.unwrap().path();
+            if let Err(e) = Driver::load(path.to_str().unwrap(), &testing::learned()).await {
+                panic!("{}", e);
+            }
         }
+    }
+
+    #[tokio::test]
+    async fn run_stops_when_cancelled() {
+        let keyboard = Arc::new(VirtualKeyboard::new());
+        let backend: Arc<dyn LightingBackend> = keyboard.clone();
+        let stream = tokio::stream::iter(vec![Ok(testing::telemetry(4500.0))])
+            .chain(tokio::stream::pending());
+
+        // The datagram is ready before the cancellation is checked again.
+        let cancel = tokio::time::delay_for(std::time::Duration::from_millis(50));
+        testing::driver(METER)
+            .run(&backend, stream, cancel, None)
+            .await
+            .unwrap();
+
+        assert_eq!(keyboard.frames().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn run_reloads_the_config_when_it_changes() {
+        let keyboard = Arc::new(VirtualKeyboard::new());
+        let backend: Arc<dyn LightingBackend> = keyboard.clone();
+        let path = testing::temp_path("reloaded.toml");
+        let meter = |row: u8| {
+            format!(
+                "[colors]\nwhite = \"ffffff\"\n{}",
+                METER.replace("row = 1", &format!("row = {}", row))
+            )
+        };
+        std::fs::write(&path, meter(1)).unwrap();
+        let path = path.to_str().unwrap();
+
+        let (datagrams, stream) = tokio::sync::mpsc::unbounded_channel();
+        let watcher = ConfigWatcher::new(path);
+        let run = Driver::load(path, &testing::learned()).await.unwrap().run(
+            &backend,
+            stream,
+            future::pending(),
+            Some(watcher),
+        );
+        // Long enough for the watcher to see a change.
+        let settle = || tokio::time::delay_for(std::time::Duration::from_secs(1));
+        let edit = async {
+            datagrams.send(Ok(testing::telemetry(4500.0))).unwrap();
+            settle().await;
+
+            // The problems are reported and the effects keep running.
+            let invalid = meter(1).replace("row = 1", "row = 9");
+            std::fs::write(path, &invalid).unwrap();
+            let error = Driver::load(path, &testing::learned()).await.err().unwrap();
+            assert_eq!(
+                error.to_string(),
+                format!(
+                    "{}:13: effect #1: output.keyboard.row: the keyboard only has 6 rows, \
+                     counting from 0",
+                    path
+                )
+            );
+            settle().await;
+            datagrams.send(Ok(testing::telemetry(4500.0))).unwrap();
+
+            std::fs::write(path, meter(2)).unwrap();
+            settle().await;
+            datagrams.send(Ok(testing::telemetry(4500.0))).unwrap();
+            datagrams.send(Ok(testing::telemetry(8000.0))).unwrap();
+            // Which ends the run.
+            drop(datagrams);
+        };
+        let (result, ()) = future::join(run, edit).await;
+        result.unwrap();
+
+        let half = [vec![WHITE; 5], vec![BLACK; 5]].concat();
+        let frames = keyboard.frames();
+        let rows: Vec<_> = frames
+            .iter()
+            .map(|frame| (testing::row(frame, 1, 0..10), testing::row(frame, 2, 0..10)))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (half.clone(), vec![BLACK; 10]),
+                (half.clone(), vec![BLACK; 10]),
+                (vec![BLACK; 10], half),
+                (vec![BLACK; 10], vec![WHITE; 10]),
+            ]
+        );
 
-        Ok(())
+        std::fs::remove_file(path).unwrap();
     }
 }
@@ -1,16 +1,31 @@
 use prelude::*;
 
-use forza::Horizon4Datagram;
+use forza::Telemetry;
+use rgb::RGB8;
 
 pub mod prelude {
     pub use crate::state::{ChromaState, Tick};
 }
 
+mod event;
+mod gear;
+mod heatmap;
+mod layout;
 mod meter;
+mod pedals;
 mod position;
+mod shift_light;
+mod vector;
 
+pub use event::*;
+pub use gear::*;
+pub use heatmap::*;
+pub use layout::*;
 pub use meter::*;
+pub use pedals::*;
 pub use position::*;
+pub use shift_light::*;
+pub use vector::*;
 
 pub struct Effect {
     altitude: i32,
@@ -40,8 +55,19 @@ pub trait EffectImpl {
 
 pub trait EffectInstance {
     /// Called when a new data state is available
-    fn update(&mut self, datagram: &Horizon4Datagram);
+    fn update(&mut self, telemetry: &Telemetry);
 
     /// Called when the chroma is being updated.
     fn tick(&mut self, tick: &Tick, state: &mut ChromaState);
 }
+
+/// Dims a color to `brightness`, from 0 for black to 1 for the color itself.
+pub fn fade(color: RGB8, brightness: f32) -> RGB8 {
+    let color: rgb::RGB<f32> = color.into();
+    let color: rgb::RGB<f32> = color * brightness;
+    RGB8 {
+        r: color.r as u8,
+        g: color.g as u8,
+        b: color.b as u8,
+    }
+}
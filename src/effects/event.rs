@@ -0,0 +1,349 @@
+use std::{collections::HashMap, time::Instant};
+
+use rgb::RGB8;
+
+use crate::{
+    config::{self, Animation, Color, KeyboardRegion, Problem, Trigger},
+    effects::{fade, EffectImpl, EffectInstance, Region},
+    filter::FilterState,
+    property::RateProperty,
+};
+
+/// How wide the ring of a ripple is, in keys.
+const RIPPLE_WIDTH: f32 = 1.5;
+
+/// Plays a short animation when something happens, e.g. a wheel touching a rumble strip.
+pub struct EventEffect {
+    property: RateProperty,
+    color: RGB8,
+    threshold: f32,
+    trigger: Trigger,
+    animation: Animation,
+    duration: f32,
+    region: Region,
+}
+
+impl EventEffect {
+    pub fn new(
+        property: Option<RateProperty>,
+        output: &config::Output,
+        config: &config::EventEffect,
+        colors: &HashMap<String, Color>,
+    ) -> Result<Self, Vec<Problem>> {
+        let mut problems = vec![];
+
+        let color = config::color(colors, &output.color).map_err(|p| problems.push(p));
+
+        if !(config.threshold > 0.0 && config.threshold <= 1.0) {
+            problems.push(Problem::new(
+                "output.threshold",
+                "the threshold is a fraction of the property's maximum, so it has to be more than \
+                 0 and at most 1",
+            ));
+        }
+        if !(config.duration > 0.0 && config.duration.is_finite()) {
+            problems.push(Problem::new(
+                "output.duration",
+                "the animation has to last a positive number of seconds",
+            ));
+        }
+
+        let keyboard = config.keyboard.clone().unwrap_or(KeyboardRegion {
+            column: config::GridRange::All,
+            row: config::GridRange::All,
+        });
+        let region = Region::from_config(
+            ("output.keyboard.column", "output.keyboard.row"),
+            &keyboard,
+            "event",
+            &mut problems,
+        );
+
+        match (property, color) {
+            (Some(property), Ok(color)) if problems.is_empty() => Ok(Self {
+                property,
+                color,
+                threshold: config.threshold,
+                trigger: config.trigger,
+                animation: config.animation,
+                duration: config.duration,
+                region,
+            }),
+            _ => Err(problems),
+        }
+    }
+
+    /// Whether the property going from `previous` to `current` sets the effect off.
+    fn triggered(&self, previous: f32, current: f32) -> bool {
+        match self.trigger {
+            Trigger::Rise => previous < self.threshold && current >= self.threshold,
+            Trigger::Jump => current - previous >= self.threshold,
+        }
+    }
+}
+
+impl EffectImpl for EventEffect {
+    fn start<'a>(&'a self) -> Box<dyn 'a + EffectInstance> {
+        Box::new(EventEffectInstance {
+            effect: self,
+            current: None,
+            filter: self.property.filter().start(),
+            previous: None,
+            started: None,
+        })
+    }
+}
+
+pub struct EventEffectInstance<'a> {
+    effect: &'a EventEffect,
+    current: Option<f32>,
+    filter: FilterState<'a>,
+    /// The filtered value at the last tick, which the trigger compares against.
+    previous: Option<f32>,
+    /// When the animation that's playing started.
+    started: Option<Instant>,
+}
+
+impl<'a> EffectInstance for EventEffectInstance<'a> {
+    fn update(&mut self, telemetry: &forza::Telemetry) {
+        self.current = if telemetry.sled.is_race_on != 0 {
+            self.effect
+                .property
+                .query(telemetry)
+                .map(|current| current.clamp(0.0, 1.0))
+        } else {
+            None
+        };
+    }
+
+    fn tick(&mut self, tick: &super::prelude::Tick, state: &mut super::prelude::ChromaState) {
+        let current = self.filter.step(self.current, tick.elapsed);
+        // Only a change counts, so a race starting with the property already high doesn't set the
+        // effect off. A new event starts the animation over.
+        if let (Some(previous), Some(current)) = (self.previous, current) {
+            if self.effect.triggered(previous, current) {
+                self.started = Some(tick.now);
+            }
+        }
+        self.previous = current;
+
+        let started = match self.started {
+            Some(started) => started,
+            None => return,
+        };
+        let progress = (tick.now - started).as_secs_f32() / self.effect.duration;
+        if progress >= 1.0 {
+            self.started = None;
+            return;
+        }
+
+        let region = &self.effect.region;
+        let keys = region
+            .rows
+            .clone()
+            .flat_map(|row| region.columns.clone().map(move |column| (row, column)));
+
+        match self.effect.animation {
+            Animation::Flash => {
+                for (row, column) in keys {
+                    state.set_position(row, column, self.effect.color);
+                }
+            }
+            Animation::Pulse => {
+                let color = fade(self.effect.color, 1.0 - progress);
+                for (row, column) in keys {
+                    state.set_position(row, column, color);
+                }
+            }
+            Animation::Ripple => {
+                let middle = |range: &std::ops::RangeInclusive<u8>| {
+                    (*range.start() as f32 + *range.end() as f32) / 2.0
+                };
+                let (middle_row, middle_column) = (middle(&region.rows), middle(&region.columns));
+                // The ring is past the furthest key when the animation ends.
+                let reach = (*region.rows.end() as f32 - middle_row)
+                    .hypot(*region.columns.end() as f32 - middle_column)
+                    + RIPPLE_WIDTH;
+                let ring = progress * reach;
+
+                for (row, column) in keys {
+                    let distance = (row as f32 - middle_row).hypot(column as f32 - middle_column);
+                    let brightness = 1.0 - (distance - ring).abs() / RIPPLE_WIDTH;
+                    if brightness > 0.0 {
+                        let color = fade(self.effect.color, brightness * (1.0 - progress));
+                        state.set_position(row, column, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{
+        driver::Driver,
+        testing::{self, BLACK, WHITE},
+    };
+
+    /// An event on the rpm, as a fraction of the range from idle to the engine's maximum, that
+    /// plays for a second on a 5×5 block in the top left.
+    fn event(trigger: &str, threshold: f32, animation: &str) -> Driver {
+        testing::driver(&format!(
+            r#"
+            [[effect]]
+            [effect.input]
+            property = "rpm-baseline"
+            [effect.output]
+            type = "event"
+            color = "white"
+            trigger = "{}"
+            threshold = {}
+            animation = "{}"
+            duration = 1.0
+            [effect.output.keyboard]
+            column = "0:4"
+            row = "0:4"
+            "#,
+            trigger, threshold, animation
+        ))
+    }
+
+    /// The rpm at `fraction` of the range from idle to the engine's maximum.
+    fn at(fraction: f32) -> forza::Telemetry {
+        testing::telemetry(1000.0 + fraction * 7000.0)
+    }
+
+    /// Steps through the rpm at each number of milliseconds, returning whether the effect showed
+    /// anything at each step.
+    fn played(driver: &Driver, steps: &[(u64, f32)]) -> Vec<bool> {
+        let mut session = driver.start();
+        let start = Instant::now();
+        steps
+            .iter()
+            .map(|&(millis, fraction)| {
+                let now = start + Duration::from_millis(millis);
+                let frame = testing::show(&mut session, &at(fraction), now);
+                frame.position(2, 2) != BLACK || frame.position(0, 0) != BLACK
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rises_trigger_when_the_threshold_is_crossed() {
+        let driver = event("rise", 0.5, "flash");
+
+        assert_eq!(
+            played(
+                &driver,
+                &[
+                    // A race that starts above the threshold doesn't set it off.
+                    (0, 0.6),
+                    (100, 0.2),
+                    (200, 0.6),
+                    // Staying above the threshold doesn't start it over.
+                    (1100, 0.8),
+                    (1300, 0.8),
+                    (1400, 0.4),
+                    (1500, 0.5),
+                ]
+            ),
+            [false, false, true, true, false, false, true]
+        );
+    }
+
+    #[test]
+    fn jumps_trigger_on_a_large_enough_increase() {
+        let driver = event("jump", 0.3, "flash");
+
+        assert_eq!(
+            played(
+                &driver,
+                &[
+                    (0, 0.1),
+                    (100, 0.3),
+                    (200, 0.5),
+                    (300, 0.8),
+                    // Staying high isn't a jump, and neither is dropping.
+                    (1400, 0.8),
+                    (1500, 0.1),
+                    (1600, 0.45),
+                ]
+            ),
+            [false, false, false, true, false, false, true]
+        );
+    }
+
+    /// The brightness of the key at `(row, column)` at each number of milliseconds after the rpm
+    /// jumps from idle to the maximum.
+    fn brightness(animation: &str, key: (u8, u8), steps: &[u64]) -> Vec<u8> {
+        let driver = event("rise", 0.5, animation);
+        let mut session = driver.start();
+        let start = Instant::now();
+        testing::show(&mut session, &at(0.0), start);
+
+        steps
+            .iter()
+            .map(|&millis| {
+                let now = start + Duration::from_millis(millis);
+                let frame = testing::show(&mut session, &at(1.0), now);
+                frame.position(key.0, key.1).g
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flashes_stay_lit_until_they_end() {
+        let driver = event("rise", 0.5, "flash");
+        let mut session = driver.start();
+        let start = Instant::now();
+        let after = |millis| start + Duration::from_millis(millis);
+        testing::show(&mut session, &at(0.0), start);
+
+        for millis in [10, 500, 1000] {
+            let frame = testing::show(&mut session, &at(1.0), after(millis));
+            for row in 0..5 {
+                assert_eq!(
+                    testing::row(&frame, row, 0..6),
+                    [WHITE, WHITE, WHITE, WHITE, WHITE, BLACK]
+                );
+            }
+        }
+
+        let frame = testing::show(&mut session, &at(1.0), after(1010));
+        assert_eq!(frame.position(2, 2), BLACK);
+    }
+
+    #[test]
+    fn pulses_fade_out() {
+        // Every key is the same.
+        assert_eq!(
+            brightness("pulse", (0, 0), &[10, 260, 510, 760, 1010]),
+            [255, 191, 127, 63, 0]
+        );
+        assert_eq!(
+            brightness("pulse", (4, 4), &[10, 260, 510, 760, 1010]),
+            [255, 191, 127, 63, 0]
+        );
+    }
+
+    #[test]
+    fn ripples_spread_out_from_the_middle_and_fade() {
+        let steps = [10, 300, 600, 900, 1010];
+        let middle = brightness("ripple", (2, 2), &steps);
+        let edge = brightness("ripple", (2, 4), &steps);
+        let corner = brightness("ripple", (0, 0), &steps);
+
+        // The ring starts in the middle and has passed it by the time it reaches the edge, and
+        // then the corner.
+        assert_eq!(middle[0], 255);
+        assert!(edge[0] < middle[0] && corner[0] == 0);
+        assert!(middle[2] == 0 && edge[2] > 0);
+        assert!(corner[3] > 0 && edge[3] < corner[3]);
+        // Every key fades as the ring spreads out, and it's gone at the end.
+        assert!(edge[2] < 255 / 2);
+        assert_eq!([middle[4], edge[4], corner[4]], [0, 0, 0]);
+    }
+}
@@ -0,0 +1,248 @@
+use std::{collections::HashMap, time::Instant};
+
+use chroma::Key;
+use rgb::RGB8;
+
+use crate::{
+    config::{self, Color, NumKeys, Problem},
+    effects::{number_keys, EffectImpl, EffectInstance},
+};
+
+/// What `dash.gear` reads in reverse.
+const REVERSE: u8 = 0;
+/// What `dash.gear` reads in neutral.
+const NEUTRAL: u8 = 11;
+
+/// Lights the key for the current gear: a number from 1 upwards, or R and N for reverse and
+/// neutral.
+pub struct GearEffect {
+    color: RGB8,
+    flash_color: RGB8,
+    flash: Option<f32>,
+    numkeys: NumKeys,
+}
+
+impl GearEffect {
+    pub fn new(
+        output: &config::Output,
+        config: &config::GearEffect,
+        colors: &HashMap<String, Color>,
+    ) -> Result<Self, Vec<Problem>> {
+        let mut problems = vec![];
+
+        let color = config::color(colors, &output.color).map_err(|p| problems.push(p));
+        let flash_color = match &config.flash_color {
+            Some(name) => config::color(colors, name).map_err(|p| {
+                problems.push(Problem::new("output.flash_color", p.message));
+            }),
+            None => color,
+        };
+
+        if config
+            .flash
+            .is_some_and(|flash| !(flash > 0.0 && flash.is_finite()))
+        {
+            problems.push(Problem::new(
+                "output.flash",
+                "the flash has to last a positive number of seconds",
+            ));
+        }
+
+        if config.keyboard.is_none() {
+            problems.push(Problem::new(
+                "output.keyboard",
+                "gear effects need an [effect.output.keyboard] table",
+            ));
+        }
+
+        match (color, flash_color, &config.keyboard) {
+            (Ok(color), Ok(flash_color), Some(keyboard)) if problems.is_empty() => Ok(Self {
+                color,
+                flash_color,
+                flash: config.flash,
+                numkeys: keyboard.numkeys,
+            }),
+            _ => Err(problems),
+        }
+    }
+
+    /// The key for `gear`, if there is one.
+    fn key(&self, gear: u8) -> Option<Key> {
+        match gear {
+            REVERSE => Some(Key::R),
+            NEUTRAL => Some(Key::N),
+            gear => number_keys(self.numkeys)
+                .get(usize::from(gear) - 1)
+                .copied(),
+        }
+    }
+}
+
+impl EffectImpl for GearEffect {
+    fn start<'a>(&'a self) -> Box<dyn 'a + EffectInstance> {
+        Box::new(GearEffectInstance {
+            effect: self,
+            current: None,
+            changed: false,
+            flash_since: None,
+        })
+    }
+}
+
+pub struct GearEffectInstance<'a> {
+    effect: &'a GearEffect,
+    current: Option<u8>,
+    /// Whether the gear changed since the last tick.
+    changed: bool,
+    /// When the last gear change was flashed.
+    flash_since: Option<Instant>,
+}
+
+impl<'a> EffectInstance for GearEffectInstance<'a> {
+    fn update(&mut self, telemetry: &forza::Telemetry) {
+        let gear = match telemetry.dash {
+            Some(dash) if telemetry.sled.is_race_on != 0 => Some(dash.gear),
+            _ => None,
+        };
+
+        // Only a change from one gear to another counts, not the race starting.
+        if let (Some(previous), Some(gear)) = (self.current, gear) {
+            self.changed |= previous != gear;
+        }
+        self.current = gear;
+    }
+
+    fn tick(&mut self, tick: &super::prelude::Tick, state: &mut super::prelude::ChromaState) {
+        let gear = match self.current {
+            Some(gear) => gear,
+            None => {
+                self.changed = false;
+                self.flash_since = None;
+                return;
+            }
+        };
+
+        if std::mem::take(&mut self.changed) {
+            self.flash_since = Some(tick.now);
+        }
+
+        if let (Some(flash), Some(since)) = (self.effect.flash, self.flash_since) {
+            if (tick.now - since).as_secs_f32() < flash {
+                for &key in number_keys(self.effect.numkeys) {
+                    state.set_key(key, self.effect.flash_color);
+                }
+                return;
+            }
+            self.flash_since = None;
+        }
+
+        if let Some(key) = self.effect.key(gear) {
+            state.set_key(key, self.effect.color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use chroma::Key;
+
+    use super::{NEUTRAL, REVERSE};
+    use crate::testing::{self, BLACK, RED, WHITE};
+
+    /// The gear on the number row, flashing red for `flash` seconds after a change.
+    fn gear(flash: Option<f32>) -> String {
+        format!(
+            r#"
+            [[effect]]
+            [effect.output]
+            type = "gear"
+            color = "white"
+            flash_color = "red"
+            {}
+            [effect.output.keyboard]
+            numkeys = "row"
+            "#,
+            flash.map_or(String::new(), |flash| format!("flash = {}", flash))
+        )
+    }
+
+    fn in_gear(gear: u8) -> forza::Telemetry {
+        let mut telemetry = testing::telemetry(1000.0);
+        telemetry.dash.as_mut().unwrap().gear = gear;
+        telemetry
+    }
+
+    /// The keys that are lit, in the order of `keys`.
+    fn lit(frame: &chroma::Frame, keys: &[Key]) -> Vec<Key> {
+        keys.iter()
+            .copied()
+            .filter(|&key| frame.key(key) != BLACK)
+            .collect()
+    }
+
+    const KEYS: [Key; 12] = [
+        Key::R,
+        Key::N,
+        Key::Row1,
+        Key::Row2,
+        Key::Row3,
+        Key::Row4,
+        Key::Row5,
+        Key::Row6,
+        Key::Row7,
+        Key::Row8,
+        Key::Row9,
+        Key::Row0,
+    ];
+
+    #[test]
+    fn every_gear_lights_its_key() {
+        let config = gear(None);
+
+        let mut gears = vec![(REVERSE, Key::R), (NEUTRAL, Key::N)];
+        gears.extend((1..=10).zip(KEYS[2..].iter().copied()));
+        for (number, key) in gears {
+            let frame = testing::render(&config, &in_gear(number));
+            assert_eq!(lit(&frame, &KEYS), [key], "gear {}", number);
+            assert_eq!(frame.key(key), WHITE);
+        }
+    }
+
+    #[test]
+    fn gear_changes_flash_and_then_show_the_gear_again() {
+        let driver = testing::driver(&gear(Some(0.2)));
+        let mut session = driver.start();
+        let start = Instant::now();
+        let after = |millis| start + Duration::from_millis(millis);
+
+        // The race starting isn't a gear change, and neither is staying in gear.
+        for millis in [0, 100] {
+            let frame = testing::show(&mut session, &in_gear(3), after(millis));
+            assert_eq!(lit(&frame, &KEYS), [Key::Row3], "at {}ms", millis);
+        }
+
+        // Every number key flashes for as long as the flash lasts, from the change on.
+        for millis in [200, 350] {
+            let frame = testing::show(&mut session, &in_gear(4), after(millis));
+            assert_eq!(lit(&frame, &KEYS), &KEYS[2..], "at {}ms", millis);
+            assert_eq!(frame.key(Key::Row4), RED);
+        }
+
+        let frame = testing::show(&mut session, &in_gear(4), after(450));
+        assert_eq!(lit(&frame, &KEYS), [Key::Row4]);
+        assert_eq!(frame.key(Key::Row4), WHITE);
+    }
+
+    #[test]
+    fn gear_changes_arent_flashed_without_a_flash() {
+        let driver = testing::driver(&gear(None));
+        let mut session = driver.start();
+        let start = Instant::now();
+
+        testing::show(&mut session, &in_gear(3), start);
+        let frame = testing::show(&mut session, &in_gear(4), start);
+        assert_eq!(lit(&frame, &KEYS), [Key::Row4]);
+    }
+}
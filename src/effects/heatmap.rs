@@ -0,0 +1,289 @@
+use std::{collections::HashMap, ops::RangeInclusive};
+
+use forza::Quad;
+use rgb::RGB8;
+
+use crate::{
+    config::{self, Color, KeyboardRegion, Problem},
+    effects::{EffectImpl, EffectInstance, Region},
+    property::QuadProperty,
+};
+
+/// Colors a block of keys for each wheel, going from cold through optimal to hot, e.g. to show the
+/// temperature of the tires.
+pub struct HeatmapEffect {
+    property: QuadProperty,
+    cold: f32,
+    optimal: f32,
+    hot: f32,
+    cold_color: RGB8,
+    color: RGB8,
+    hot_color: RGB8,
+    regions: Quad<Region>,
+}
+
+impl HeatmapEffect {
+    pub fn new(
+        property: Option<QuadProperty>,
+        output: &config::Output,
+        config: &config::HeatmapEffect,
+        colors: &HashMap<String, Color>,
+    ) -> Result<Self, Vec<Problem>> {
+        let mut problems = vec![];
+
+        let color = config::color(colors, &output.color).map_err(|p| problems.push(p));
+        let cold_color = config::color(colors, &config.cold_color).map_err(|p| {
+            problems.push(Problem::new("output.cold_color", p.message));
+        });
+        let hot_color = config::color(colors, &config.hot_color).map_err(|p| {
+            problems.push(Problem::new("output.hot_color", p.message));
+        });
+
+        if !(config.cold < config.optimal && config.optimal < config.hot) {
+            problems.push(Problem::new(
+                "output.optimal",
+                format!(
+                    "cold ({}), optimal ({}) and hot ({}) have to go up in that order",
+                    config.cold, config.optimal, config.hot
+                ),
+            ));
+        }
+
+        let keyboard = config.keyboard.clone().unwrap_or_default();
+        let (columns, rows) = (chroma::MAX_COLUMN, chroma::MAX_ROW);
+        let (left, right) = (0..=columns / 2 - 1, columns / 2..=columns - 1);
+        let (front, rear) = (0..=rows / 2 - 1, rows / 2..=rows - 1);
+        let regions = Quad {
+            front_left: region(
+                ("output.keyboard.front_left.column", left.clone()),
+                ("output.keyboard.front_left.row", front.clone()),
+                keyboard.front_left.as_ref(),
+                &mut problems,
+            ),
+            front_right: region(
+                ("output.keyboard.front_right.column", right.clone()),
+                ("output.keyboard.front_right.row", front),
+                keyboard.front_right.as_ref(),
+                &mut problems,
+            ),
+            rear_left: region(
+                ("output.keyboard.rear_left.column", left),
+                ("output.keyboard.rear_left.row", rear.clone()),
+                keyboard.rear_left.as_ref(),
+                &mut problems,
+            ),
+            rear_right: region(
+                ("output.keyboard.rear_right.column", right),
+                ("output.keyboard.rear_right.row", rear),
+                keyboard.rear_right.as_ref(),
+                &mut problems,
+            ),
+        };
+
+        match (property, color, cold_color, hot_color) {
+            (Some(property), Ok(color), Ok(cold_color), Ok(hot_color)) if problems.is_empty() => {
+                Ok(Self {
+                    property,
+                    cold: config.cold,
+                    optimal: config.optimal,
+                    hot: config.hot,
+                    cold_color,
+                    color,
+                    hot_color,
+                    regions,
+                })
+            }
+            _ => Err(problems),
+        }
+    }
+
+    fn color(&self, value: f32) -> RGB8 {
+        if value <= self.optimal {
+            let t = (value - self.cold) / (self.optimal - self.cold);
+            blend(self.cold_color, self.color, t)
+        } else {
+            let t = (value - self.optimal) / (self.hot - self.optimal);
+            blend(self.color, self.hot_color, t)
+        }
+    }
+}
+
+/// The keys for a wheel, which are given by the config or else default to `column` and `row`.
+fn region(
+    (column_key, column): (&'static str, RangeInclusive<u8>),
+    (row_key, row): (&'static str, RangeInclusive<u8>),
+    config: Option<&KeyboardRegion>,
+    problems: &mut Vec<Problem>,
+) -> Region {
+    match config {
+        Some(config) => Region::from_config((column_key, row_key), config, "heatmap", problems),
+        None => Region {
+            columns: column,
+            rows: row,
+        },
+    }
+}
+
+/// The color `t` of the way from `from` to `to`.
+fn blend(from: RGB8, to: RGB8, t: f32) -> RGB8 {
+    let t = t.clamp(0.0, 1.0);
+    let channel = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * t).round() as u8;
+    RGB8::new(
+        channel(from.r, to.r),
+        channel(from.g, to.g),
+        channel(from.b, to.b),
+    )
+}
+
+impl EffectImpl for HeatmapEffect {
+    fn start<'a>(&'a self) -> Box<dyn 'a + EffectInstance> {
+        Box::new(HeatmapEffectInstance {
+            effect: self,
+            current: None,
+        })
+    }
+}
+
+pub struct HeatmapEffectInstance<'a> {
+    effect: &'a HeatmapEffect,
+    current: Option<Quad<f32>>,
+}
+
+impl<'a> EffectInstance for HeatmapEffectInstance<'a> {
+    fn update(&mut self, telemetry: &forza::Telemetry) {
+        self.current = if telemetry.sled.is_race_on != 0 {
+            self.effect.property.query(telemetry)
+        } else {
+            None
+        };
+    }
+
+    fn tick(&mut self, _tick: &super::prelude::Tick, state: &mut super::prelude::ChromaState) {
+        let current = match self.current {
+            Some(current) => current,
+            None => return,
+        };
+
+        let regions = &self.effect.regions;
+        for (value, region) in [
+            (current.front_left, &regions.front_left),
+            (current.front_right, &regions.front_right),
+            (current.rear_left, &regions.rear_left),
+            (current.rear_right, &regions.rear_right),
+        ] {
+            let color = self.effect.color(value);
+            for row in region.rows.clone() {
+                for column in region.columns.clone() {
+                    state.set_position(row, column, color);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use forza::Quad;
+    use rgb::RGB8;
+
+    use crate::testing::{self, BLUE, RED, WHITE};
+
+    /// The tire temperatures, blue when cold, white at 200°F and red when hot.
+    fn heatmap(cold: f32, optimal: f32, hot: f32) -> String {
+        format!(
+            r#"
+            [[effect]]
+            [effect.input]
+            property = "dash.tire_temp"
+            [effect.output]
+            type = "heatmap"
+            color = "white"
+            cold_color = "blue"
+            hot_color = "red"
+            cold = {}
+            optimal = {}
+            hot = {}
+            "#,
+            cold, optimal, hot
+        )
+    }
+
+    fn temperatures(temperatures: Quad<f32>) -> forza::Telemetry {
+        let mut telemetry = testing::telemetry(1000.0);
+        telemetry.dash.as_mut().unwrap().tire_temp = temperatures;
+        telemetry
+    }
+
+    /// Every wheel at `temperature`, which is shown on the top left key among others.
+    fn color_at(temperature: f32) -> RGB8 {
+        let all = Quad {
+            front_left: temperature,
+            front_right: temperature,
+            rear_left: temperature,
+            rear_right: temperature,
+        };
+        testing::render(&heatmap(100.0, 200.0, 300.0), &temperatures(all)).position(0, 0)
+    }
+
+    #[test]
+    fn temperatures_blend_from_cold_through_optimal_to_hot() {
+        assert_eq!(color_at(50.0), BLUE);
+        assert_eq!(color_at(100.0), BLUE);
+        assert_eq!(color_at(150.0), RGB8::new(128, 128, 255));
+        assert_eq!(color_at(200.0), WHITE);
+        assert_eq!(color_at(250.0), RGB8::new(255, 128, 128));
+        assert_eq!(color_at(300.0), RED);
+        assert_eq!(color_at(400.0), RED);
+    }
+
+    #[test]
+    fn each_wheel_defaults_to_a_quarter_of_the_keyboard() {
+        let frame = testing::render(
+            &heatmap(100.0, 200.0, 300.0),
+            &temperatures(Quad {
+                front_left: 100.0,
+                front_right: 200.0,
+                rear_left: 300.0,
+                rear_right: 150.0,
+            }),
+        );
+
+        let rear_right = RGB8::new(128, 128, 255);
+        for row in 0..chroma::MAX_ROW {
+            let (left, right) = if row < chroma::MAX_ROW / 2 {
+                (BLUE, WHITE)
+            } else {
+                (RED, rear_right)
+            };
+            let mut expected = vec![left; 11];
+            expected.extend([right; 11]);
+            assert_eq!(
+                testing::row(&frame, row, 0..chroma::MAX_COLUMN),
+                expected,
+                "row {}",
+                row
+            );
+        }
+    }
+
+    #[test]
+    fn thresholds_have_to_go_up() {
+        for (cold, optimal, hot) in [
+            (200.0, 100.0, 300.0),
+            (100.0, 300.0, 200.0),
+            (100.0, 100.0, 300.0),
+            (300.0, 200.0, 100.0),
+        ] {
+            let problems = testing::problems(&heatmap(cold, optimal, hot));
+            assert_eq!(problems.len(), 1);
+            assert_eq!(problems[0].key, "output.optimal");
+            assert_eq!(
+                problems[0].message,
+                format!(
+                    "cold ({}), optimal ({}) and hot ({}) have to go up in that order",
+                    cold, optimal, hot
+                )
+            );
+        }
+    }
+}
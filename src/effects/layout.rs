@@ -0,0 +1,198 @@
+use std::ops::RangeInclusive;
+
+use crate::config::{GridRange, KeyboardMeter, KeyboardRegion, Problem};
+
+#[derive(Copy, Clone)]
+enum MeterOrientation {
+    ColumnBase,
+    RowBase,
+}
+
+/// The keys of a meter: a line of keys that fills up in one direction, repeated across a range of
+/// rows or columns.
+#[derive(Clone)]
+pub struct MeterLayout {
+    orientation: MeterOrientation,
+    base: RangeInclusive<u8>,
+    meter: RangeInclusive<u8>,
+}
+
+/// A block of keys.
+pub struct Region {
+    pub columns: RangeInclusive<u8>,
+    pub rows: RangeInclusive<u8>,
+}
+
+impl Region {
+    /// `keys` are the keys of the column and the row in problems, and `kind` names the effect,
+    /// e.g. "heatmap". Problems are added to `problems`.
+    pub fn from_config(
+        (column_key, row_key): (&'static str, &'static str),
+        config: &KeyboardRegion,
+        kind: &str,
+        problems: &mut Vec<Problem>,
+    ) -> Self {
+        problems.extend(check_column(column_key, &config.column));
+        problems.extend(check_row(row_key, &config.row));
+
+        Self {
+            columns: span(
+                column_key,
+                &config.column,
+                chroma::MAX_COLUMN,
+                kind,
+                problems,
+            ),
+            rows: span(row_key, &config.row, chroma::MAX_ROW, kind, problems),
+        }
+    }
+}
+
+/// The range in increasing order, whichever way round the config wrote it.
+fn span(
+    key: &'static str,
+    range: &GridRange,
+    max: u8,
+    kind: &str,
+    problems: &mut Vec<Problem>,
+) -> RangeInclusive<u8> {
+    match range {
+        GridRange::Range(range) => {
+            *range.start().min(range.end())..=*range.start().max(range.end())
+        }
+        GridRange::All => 0..=max - 1,
+        GridRange::Direction(_) => {
+            problems.push(Problem::new(
+                key,
+                format!(
+                    "{} regions don't have a direction (e.g. use x or x:y)",
+                    kind
+                ),
+            ));
+            0..=0
+        }
+    }
+}
+
+/// Points out a range of columns that goes past the edge of the keyboard.
+fn check_column(key: &'static str, range: &GridRange) -> Option<Problem> {
+    check_range(key, range, chroma::MAX_COLUMN, "columns")
+}
+
+/// Points out a range of rows that goes past the edge of the keyboard.
+pub(super) fn check_row(key: &'static str, range: &GridRange) -> Option<Problem> {
+    check_range(key, range, chroma::MAX_ROW, "rows")
+}
+
+fn check_range(key: &'static str, range: &GridRange, max: u8, name: &str) -> Option<Problem> {
+    let out_of_range = match range {
+        GridRange::Range(range) | GridRange::Direction(range) => {
+            *range.start().max(range.end()) >= max
+        }
+        GridRange::All => false,
+    };
+
+    out_of_range.then(|| {
+        Problem::new(
+            key,
+            format!("the keyboard only has {} {}, counting from 0", max, name),
+        )
+    })
+}
+
+impl MeterLayout {
+    /// `kind` names the effect in problems, e.g. "meter".
+    pub fn new(keyboard: Option<&KeyboardMeter>, kind: &str) -> Result<Self, Vec<Problem>> {
+        let keyboard = match keyboard {
+            Some(keyboard) => keyboard,
+            None => {
+                return Err(vec![Problem::new(
+                    "output.keyboard",
+                    format!("{} effects need an [effect.output.keyboard] table", kind),
+                )]);
+            }
+        };
+
+        Self::from_ranges(
+            "output.keyboard",
+            ("output.keyboard.column", &keyboard.column),
+            ("output.keyboard.row", &keyboard.row),
+        )
+    }
+
+    /// Lays out a meter from a `column` and `row`, each given along with its key in problems.
+    /// `table` is the key of the table that they're in.
+    pub fn from_ranges(
+        table: &'static str,
+        (column_key, column): (&'static str, &GridRange),
+        (row_key, row): (&'static str, &GridRange),
+    ) -> Result<Self, Vec<Problem>> {
+        let mut problems = vec![];
+
+        problems.extend(check_column(column_key, column));
+        problems.extend(check_row(row_key, row));
+
+        let column_range = match column {
+            GridRange::All => GridRange::Range(0..=chroma::MAX_COLUMN - 1),
+            x => x.clone(),
+        };
+
+        let row_range = match row {
+            GridRange::All => GridRange::Range(0..=chroma::MAX_ROW - 1),
+            x => x.clone(),
+        };
+
+        let (orientation, base, meter) = match (column_range, row_range) {
+            (GridRange::Range(base), GridRange::Direction(meter)) => {
+                (MeterOrientation::ColumnBase, base, meter)
+            }
+            (GridRange::Direction(meter), GridRange::Range(base)) => {
+                (MeterOrientation::RowBase, base, meter)
+            }
+            _ => {
+                problems.push(Problem::new(
+                    table,
+                    "one of column and row must be a direction (e.g. x->y) and the other must \
+                     not be (e.g. x or x:y)",
+                ));
+                return Err(problems);
+            }
+        };
+
+        if problems.is_empty() {
+            Ok(Self {
+                orientation,
+                base,
+                meter,
+            })
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// The number of keys in the direction that the meter fills up in.
+    pub fn length(&self) -> u8 {
+        if self.meter.start() > self.meter.end() {
+            self.meter.start() + 1 - self.meter.end()
+        } else {
+            self.meter.end() + 1 - self.meter.start()
+        }
+    }
+
+    /// The `(row, column)` of the key `step` keys into the meter, for every row or column that the
+    /// meter covers.
+    pub fn positions(&self, step: u8) -> impl Iterator<Item = (u8, u8)> {
+        let meter = &self.meter;
+        let extent = if meter.start() > meter.end() {
+            meter.start() - step
+        } else {
+            meter.start() + step
+        };
+
+        let orientation = self.orientation;
+        self.base.clone().map(move |base| match orientation {
+            MeterOrientation::RowBase => (base, extent),
+            MeterOrientation::ColumnBase => (extent, base),
+        })
+    }
+}
@@ -1,74 +1,42 @@
-use std::{collections::HashMap, ops::RangeInclusive};
+use std::collections::HashMap;
 
 use rgb::RGB8;
 
 use crate::{
-    config::{self, Color, GridRange},
-    effects::{EffectImpl, EffectInstance},
+    config::{self, Color, Problem},
+    effects::{fade, EffectImpl, EffectInstance, MeterLayout},
+    filter::FilterState,
     property::RateProperty,
 };
 
-#[derive(Copy, Clone)]
-enum MeterOrientation {
-    ColumnBase,
-    RowBase,
-}
-
 pub struct MeterEffect {
     property: RateProperty,
     color: RGB8,
-    orientation: MeterOrientation,
-    base: RangeInclusive<u8>,
-    meter: RangeInclusive<u8>,
+    layout: MeterLayout,
     fill: bool,
 }
 
 impl MeterEffect {
     pub fn new(
-        property: RateProperty,
+        property: Option<RateProperty>,
         output: &config::Output,
         config: &config::MeterEffect,
         colors: &HashMap<String, Color>,
-    ) -> Self {
-        let keyboard = config
-            .keyboard
-            .as_ref()
-            .expect("TODO: We shouldn't require a keyboard output for MeterEffect.");
-
-        let color = colors[&output.color].0;
-
-        let column_range = match &keyboard.column {
-            GridRange::All => GridRange::Range(0..=chroma::MAX_COLUMN - 1),
-            x => x.clone(),
-        };
-
-        let row_range = match &keyboard.row {
-            GridRange::All => GridRange::Range(0..=chroma::MAX_ROW - 1),
-            x => x.clone(),
-        };
+    ) -> Result<Self, Vec<Problem>> {
+        let mut problems = vec![];
 
-        let (orientation, base, meter) = match (column_range, row_range) {
-            (GridRange::Range(base), GridRange::Direction(meter)) => {
-                (MeterOrientation::ColumnBase, base, meter)
-            }
-            (GridRange::Direction(meter), GridRange::Range(base)) => {
-                (MeterOrientation::RowBase, base, meter)
-            }
-            _ => {
-                panic!(
-                    "One of the ranges for keyboard output must be direction (e.g. x->y) and \
-                     the other must be non-direction (e.g. x or x:y)"
-                );
-            }
-        };
+        let color = config::color(colors, &output.color).map_err(|p| problems.push(p));
+        let layout =
+            MeterLayout::new(config.keyboard.as_ref(), "meter").map_err(|p| problems.extend(p));
 
-        Self {
-            property,
-            color,
-            orientation,
-            base,
-            meter,
-            fill: config.fill,
+        match (property, color, layout) {
+            (Some(property), Ok(color), Ok(layout)) if problems.is_empty() => Ok(Self {
+                property,
+                color,
+                layout,
+                fill: config.fill,
+            }),
+            _ => Err(problems),
         }
     }
 }
@@ -76,8 +44,9 @@ impl MeterEffect {
 impl EffectImpl for MeterEffect {
     fn start<'a>(&'a self) -> Box<dyn 'a + EffectInstance> {
         Box::new(MeterEffectInstance {
-            effect: &self,
+            effect: self,
             current: None,
+            filter: self.property.filter().start(),
         })
     }
 }
@@ -85,79 +54,204 @@ impl EffectImpl for MeterEffect {
 pub struct MeterEffectInstance<'a> {
     effect: &'a MeterEffect,
     current: Option<f32>,
+    filter: FilterState<'a>,
 }
 
 impl<'a> EffectInstance for MeterEffectInstance<'a> {
-    fn update(&mut self, datagram: &forza::Horizon4Datagram) {
-        self.current = if datagram.sled.is_race_on != 0 {
-            Some(self.effect.property.query(datagram).max(0.0).min(1.0))
+    fn update(&mut self, telemetry: &forza::Telemetry) {
+        self.current = if telemetry.sled.is_race_on != 0 {
+            self.effect
+                .property
+                .query(telemetry)
+                .map(|current| current.clamp(0.0, 1.0))
         } else {
             None
         };
     }
 
-    fn tick(&mut self, _tick: &super::prelude::Tick, state: &mut super::prelude::ChromaState) {
-        let pct_rpm = if let Some(current) = self.current {
+    fn tick(&mut self, tick: &super::prelude::Tick, state: &mut super::prelude::ChromaState) {
+        let pct_rpm = if let Some(current) = self.filter.step(self.current, tick.elapsed) {
             current
         } else {
             return;
         };
 
-        let orientation = self.effect.orientation;
-        let color = self.effect.color;
-        let meter = self.effect.meter.clone();
-        let base = self.effect.base.clone();
+        draw(
+            &self.effect.layout,
+            self.effect.fill,
+            self.effect.color,
+            pct_rpm,
+            state,
+        );
+    }
+}
 
-        let meter_height = if meter.start() > meter.end() {
-            meter.start() + 1 - meter.end()
-        } else {
-            meter.end() + 1 - meter.start()
-        } as f32;
-
-        let shade = meter_height * pct_rpm;
-        // round down
-        let num_filled = shade as u8;
-        for base in base {
-            let extent = |position: u8| {
-                if meter.start() > meter.end() {
-                    meter.start() - position
-                } else {
-                    meter.start() + position
-                }
-            };
-
-            if self.effect.fill {
-                for position in 0..num_filled {
-                    let (row, column) = match orientation {
-                        MeterOrientation::RowBase => (base, extent(position)),
-                        MeterOrientation::ColumnBase => (extent(position), base),
-                    };
-
-                    state.set_position(row, column, color);
-                }
-            }
+/// Draws a meter that's `value` of the way full, from 0.0 to 1.0.
+pub(super) fn draw(
+    layout: &MeterLayout,
+    fill: bool,
+    color: RGB8,
+    value: f32,
+    state: &mut super::prelude::ChromaState,
+) {
+    let length = layout.length();
 
-            // In case we manage to hit 100%...
-            if num_filled < chroma::MAX_COLUMN {
-                let (row, column) = match orientation {
-                    MeterOrientation::RowBase => (base, extent(num_filled)),
-                    MeterOrientation::ColumnBase => (extent(num_filled), base),
-                };
-
-                let color = if self.effect.fill {
-                    let color: rgb::RGB<f32> = color.into();
-                    let color: rgb::RGB<f32> = color * (shade % 1.0);
-                    RGB8 {
-                        r: color.r as u8,
-                        g: color.g as u8,
-                        b: color.b as u8,
-                    }
-                } else {
-                    color
-                };
+    let shade = length as f32 * value;
+    // round down
+    let num_filled = (shade as u8).min(length);
 
+    if fill {
+        for position in 0..num_filled {
+            for (row, column) in layout.positions(position) {
                 state.set_position(row, column, color);
             }
         }
     }
+
+    // The key at the tip of the meter, which is only partly lit when filling. A full meter
+    // has no tip, unless it isn't filled, in which case the last key stays lit.
+    let (tip, color) = if num_filled < length {
+        let color = if fill {
+            fade(color, shade % 1.0)
+        } else {
+            color
+        };
+        (num_filled, color)
+    } else if !fill {
+        (length - 1, color)
+    } else {
+        return;
+    };
+
+    for (row, column) in layout.positions(tip) {
+        state.set_position(row, column, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rgb::RGB8;
+
+    use crate::testing::{self, BLACK, WHITE};
+
+    /// A meter of the rpm from idle to the redline, along the first ten keys of the second row.
+    fn meter(fill: bool, column: &str) -> String {
+        format!(
+            r#"
+            [[effect]]
+            [effect.input]
+            property = "rpm-baseline"
+            [effect.output]
+            type = "meter"
+            color = "white"
+            fill = {}
+            [effect.output.keyboard]
+            column = "{}"
+            row = 1
+            "#,
+            fill, column
+        )
+    }
+
+    #[test]
+    fn filled_meters_light_every_key_up_to_the_value() {
+        let frame = testing::render(&meter(true, "0->9"), &testing::telemetry(4500.0));
+
+        let mut expected = vec![WHITE; 5];
+        expected.extend([BLACK; 5]);
+        assert_eq!(testing::row(&frame, 1, 0..10), expected);
+        // Nothing outside of the meter is touched.
+        assert_eq!(frame.position(0, 0), BLACK);
+        assert_eq!(frame.position(1, 10), BLACK);
+    }
+
+    #[test]
+    fn filled_meters_shade_the_tip() {
+        let frame = testing::render(&meter(true, "0->9"), &testing::telemetry(4850.0));
+
+        // 5.5 keys.
+        assert_eq!(frame.position(1, 4), WHITE);
+        assert_eq!(frame.position(1, 5), RGB8::new(127, 127, 127));
+        assert_eq!(frame.position(1, 6), BLACK);
+    }
+
+    #[test]
+    fn unfilled_meters_only_light_the_tip() {
+        let frame = testing::render(&meter(false, "0->9"), &testing::telemetry(4500.0));
+
+        let mut expected = vec![BLACK; 10];
+        expected[5] = WHITE;
+        assert_eq!(testing::row(&frame, 1, 0..10), expected);
+    }
+
+    #[test]
+    fn full_meters_light_every_key() {
+        // At the redline, and past it.
+        for rpm in [8000.0, 9000.0] {
+            let frame = testing::render(&meter(true, "0->9"), &testing::telemetry(rpm));
+            assert_eq!(testing::row(&frame, 1, 0..10), vec![WHITE; 10]);
+            assert_eq!(frame.position(1, 10), BLACK);
+
+            let frame = testing::render(&meter(true, "9->0"), &testing::telemetry(rpm));
+            assert_eq!(testing::row(&frame, 1, 0..10), vec![WHITE; 10]);
+        }
+    }
+
+    #[test]
+    fn full_unfilled_meters_light_their_last_key() {
+        for rpm in [8000.0, 9000.0] {
+            let frame = testing::render(&meter(false, "0->9"), &testing::telemetry(rpm));
+            let mut expected = vec![BLACK; 10];
+            expected[9] = WHITE;
+            assert_eq!(testing::row(&frame, 1, 0..10), expected);
+            assert_eq!(frame.position(1, 10), BLACK);
+
+            let frame = testing::render(&meter(false, "9->0"), &testing::telemetry(rpm));
+            let mut expected = vec![BLACK; 10];
+            expected[0] = WHITE;
+            assert_eq!(testing::row(&frame, 1, 0..10), expected);
+        }
+    }
+
+    #[test]
+    fn empty_unfilled_meters_light_their_first_key() {
+        let frame = testing::render(&meter(false, "0->9"), &testing::telemetry(1000.0));
+
+        let mut expected = vec![BLACK; 10];
+        expected[0] = WHITE;
+        assert_eq!(testing::row(&frame, 1, 0..10), expected);
+    }
+
+    #[test]
+    fn reversed_meters_fill_from_the_other_end() {
+        let frame = testing::render(&meter(true, "9->0"), &testing::telemetry(4500.0));
+
+        let mut expected = vec![BLACK; 5];
+        expected.extend([WHITE; 5]);
+        assert_eq!(testing::row(&frame, 1, 0..10), expected);
+    }
+
+    #[test]
+    fn meters_can_fill_columns() {
+        let config = r#"
+            [[effect]]
+            [effect.input]
+            property = "rpm-baseline"
+            [effect.output]
+            type = "meter"
+            color = "white"
+            fill = true
+            [effect.output.keyboard]
+            column = "2:3"
+            row = "5->0"
+        "#;
+        let frame = testing::render(config, &testing::telemetry(4500.0));
+
+        for column in 2..=3 {
+            let lit: Vec<_> = (0..6).map(|row| frame.position(row, column)).collect();
+            assert_eq!(lit, [BLACK, BLACK, BLACK, WHITE, WHITE, WHITE]);
+        }
+        assert_eq!(frame.position(5, 1), BLACK);
+        assert_eq!(frame.position(5, 4), BLACK);
+    }
 }
@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+
+use forza::Dash;
+use rgb::RGB8;
+
+use crate::{
+    config::{self, Color, GridRange, Pedal, Problem},
+    effects::{layout, meter, EffectImpl, EffectInstance, MeterLayout},
+};
+
+#[derive(Copy, Clone)]
+enum PedalKind {
+    Accel,
+    Brake,
+    Clutch,
+    HandBrake,
+}
+
+impl PedalKind {
+    /// How far the pedal is pressed, from 0.0 to 1.0.
+    fn read(self, dash: &Dash) -> f32 {
+        let value = match self {
+            PedalKind::Accel => dash.accel,
+            PedalKind::Brake => dash.brake,
+            PedalKind::Clutch => dash.clutch,
+            PedalKind::HandBrake => dash.hand_brake,
+        };
+        value as f32 / u8::MAX as f32
+    }
+
+    /// The column and color that the pedal is shown in unless the config says otherwise.
+    fn default(self) -> (u8, RGB8) {
+        match self {
+            PedalKind::Accel => (21, RGB8::new(0, 255, 0)),
+            PedalKind::Brake => (20, RGB8::new(255, 0, 0)),
+            PedalKind::Clutch => (19, RGB8::new(0, 128, 255)),
+            PedalKind::HandBrake => (18, RGB8::new(255, 200, 0)),
+        }
+    }
+
+    /// The keys of the pedal's table, column and color in problems.
+    fn keys(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            PedalKind::Accel => (
+                "output.pedals.accel",
+                "output.pedals.accel.column",
+                "output.pedals.accel.color",
+            ),
+            PedalKind::Brake => (
+                "output.pedals.brake",
+                "output.pedals.brake.column",
+                "output.pedals.brake.color",
+            ),
+            PedalKind::Clutch => (
+                "output.pedals.clutch",
+                "output.pedals.clutch.column",
+                "output.pedals.clutch.color",
+            ),
+            PedalKind::HandBrake => (
+                "output.pedals.hand_brake",
+                "output.pedals.hand_brake.column",
+                "output.pedals.hand_brake.color",
+            ),
+        }
+    }
+}
+
+struct Bar {
+    pedal: PedalKind,
+    color: RGB8,
+    layout: MeterLayout,
+}
+
+/// Shows how far each pedal is pressed as a bar that fills up.
+pub struct PedalsEffect {
+    bars: Vec<Bar>,
+}
+
+impl PedalsEffect {
+    pub fn new(
+        config: &config::PedalsEffect,
+        colors: &HashMap<String, Color>,
+    ) -> Result<Self, Vec<Problem>> {
+        let mut problems = vec![];
+
+        // Otherwise every bar would have the same problem.
+        if !matches!(config.row, GridRange::Direction(_)) {
+            problems.push(Problem::new(
+                "output.row",
+                "the bars need a direction to fill up in (e.g. 5->1)",
+            ));
+            return Err(problems);
+        }
+        if let Some(problem) = layout::check_row("output.row", &config.row) {
+            problems.push(problem);
+            return Err(problems);
+        }
+
+        let pedals = match &config.pedals {
+            Some(pedals) => vec![
+                (PedalKind::Accel, pedals.accel.clone()),
+                (PedalKind::Brake, pedals.brake.clone()),
+                (PedalKind::Clutch, pedals.clutch.clone()),
+                (PedalKind::HandBrake, pedals.hand_brake.clone()),
+            ],
+            None => vec![
+                (PedalKind::Accel, Some(Pedal::default())),
+                (PedalKind::Brake, Some(Pedal::default())),
+                (PedalKind::Clutch, Some(Pedal::default())),
+                (PedalKind::HandBrake, Some(Pedal::default())),
+            ],
+        };
+
+        let mut bars = vec![];
+        for (pedal, config_pedal) in pedals {
+            let config_pedal = match config_pedal {
+                Some(config_pedal) => config_pedal,
+                None => continue,
+            };
+            let (table, column_key, color_key) = pedal.keys();
+            let (default_column, default_color) = pedal.default();
+
+            let color = match &config_pedal.color {
+                Some(name) => config::color(colors, name).map_err(|p| {
+                    problems.push(Problem::new(color_key, p.message));
+                }),
+                None => Ok(default_color),
+            };
+            let column = config_pedal
+                .column
+                .unwrap_or(GridRange::Range(default_column..=default_column));
+            let layout =
+                MeterLayout::from_ranges(table, (column_key, &column), ("output.row", &config.row))
+                    .map_err(|p| problems.extend(p));
+
+            if let (Ok(color), Ok(layout)) = (color, layout) {
+                bars.push(Bar {
+                    pedal,
+                    color,
+                    layout,
+                });
+            }
+        }
+
+        if config.pedals.is_some() && bars.is_empty() && problems.is_empty() {
+            problems.push(Problem::new(
+                "output.pedals",
+                "there aren't any pedals to show - add a table for each one, e.g. \
+                 [effect.output.pedals.brake]",
+            ));
+        }
+
+        if problems.is_empty() {
+            Ok(Self { bars })
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+impl EffectImpl for PedalsEffect {
+    fn start<'a>(&'a self) -> Box<dyn 'a + EffectInstance> {
+        Box::new(PedalsEffectInstance {
+            effect: self,
+            current: None,
+        })
+    }
+}
+
+pub struct PedalsEffectInstance<'a> {
+    effect: &'a PedalsEffect,
+    current: Option<Dash>,
+}
+
+impl<'a> EffectInstance for PedalsEffectInstance<'a> {
+    fn update(&mut self, telemetry: &forza::Telemetry) {
+        self.current = if telemetry.sled.is_race_on != 0 {
+            telemetry.dash
+        } else {
+            None
+        };
+    }
+
+    fn tick(&mut self, _tick: &super::prelude::Tick, state: &mut super::prelude::ChromaState) {
+        let dash = match &self.current {
+            Some(dash) => dash,
+            None => return,
+        };
+
+        for bar in &self.effect.bars {
+            meter::draw(&bar.layout, true, bar.color, bar.pedal.read(dash), state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rgb::RGB8;
+
+    use crate::testing::{self, BLACK, WHITE};
+
+    const GREEN: RGB8 = RGB8::new(0, 255, 0);
+    const RED: RGB8 = RGB8::new(255, 0, 0);
+    const BLUE: RGB8 = RGB8::new(0, 128, 255);
+    const YELLOW: RGB8 = RGB8::new(255, 200, 0);
+
+    fn pedals(config: &str) -> String {
+        format!(
+            r#"
+            [[effect]]
+            [effect.output]
+            type = "pedals"
+            {}
+            "#,
+            config
+        )
+    }
+
+    /// The pedals pressed from 0 for not at all to 255 for all the way.
+    fn pressed(accel: u8, brake: u8, clutch: u8, hand_brake: u8) -> forza::Telemetry {
+        let mut telemetry = testing::telemetry(1000.0);
+        let dash = telemetry.dash.as_mut().unwrap();
+        dash.accel = accel;
+        dash.brake = brake;
+        dash.clutch = clutch;
+        dash.hand_brake = hand_brake;
+        telemetry
+    }
+
+    /// The colors up a column, from the bottom row.
+    fn column(frame: &chroma::Frame, column: u8) -> Vec<RGB8> {
+        (0..chroma::MAX_ROW)
+            .rev()
+            .map(|row| frame.position(row, column))
+            .collect()
+    }
+
+    /// The bottom `keys` of the five rows from 5 up to 1 in `color`.
+    fn bar(keys: usize, color: RGB8) -> Vec<RGB8> {
+        let mut bar = vec![BLACK; 6];
+        bar[..keys].fill(color);
+        bar
+    }
+
+    #[test]
+    fn pedals_default_to_the_numpad_columns() {
+        let frame = testing::render(&pedals(""), &pressed(255, 255, 255, 255));
+
+        assert_eq!(column(&frame, 21), bar(5, GREEN));
+        assert_eq!(column(&frame, 20), bar(5, RED));
+        assert_eq!(column(&frame, 19), bar(5, BLUE));
+        assert_eq!(column(&frame, 18), bar(5, YELLOW));
+        assert_eq!(column(&frame, 17), bar(0, BLACK));
+    }
+
+    #[test]
+    fn bars_fill_up_as_the_pedals_are_pressed() {
+        let frame = testing::render(&pedals(""), &pressed(255, 0, 0, 0));
+
+        assert_eq!(column(&frame, 21), bar(5, GREEN));
+        for pedal in 18..=20 {
+            assert_eq!(column(&frame, pedal), bar(0, BLACK));
+        }
+    }
+
+    #[test]
+    fn only_the_configured_pedals_are_shown() {
+        let config = pedals(
+            r#"
+            row = "4->0"
+            [effect.output.pedals.brake]
+            column = "2:3"
+            color = "white"
+            [effect.output.pedals.accel]
+            "#,
+        );
+        let frame = testing::render(&config, &pressed(255, 255, 255, 255));
+
+        let mut brake = bar(0, BLACK);
+        brake[1..].fill(WHITE);
+        assert_eq!(column(&frame, 2), brake);
+        assert_eq!(column(&frame, 3), brake);
+        let mut accel = bar(0, BLACK);
+        accel[1..].fill(GREEN);
+        assert_eq!(column(&frame, 21), accel);
+        for pedal in 18..=20 {
+            assert_eq!(column(&frame, pedal), bar(0, BLACK));
+        }
+    }
+
+    #[test]
+    fn rows_and_columns_have_to_be_on_the_keyboard() {
+        let problem = |config| {
+            let problems = testing::problems(&pedals(config));
+            problems
+                .iter()
+                .map(|problem| (problem.key, problem.message.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            problem("row = 2"),
+            [(
+                "output.row",
+                "the bars need a direction to fill up in (e.g. 5->1)".to_owned()
+            )]
+        );
+        // Once, rather than for every pedal.
+        assert_eq!(
+            problem("row = \"6->1\""),
+            [(
+                "output.row",
+                "the keyboard only has 6 rows, counting from 0".to_owned()
+            )]
+        );
+        assert_eq!(
+            problem("[effect.output.pedals.clutch]\ncolumn = \"20:22\""),
+            [(
+                "output.pedals.clutch.column",
+                "the keyboard only has 22 columns, counting from 0".to_owned()
+            )]
+        );
+        assert_eq!(
+            problem("[effect.output.pedals.clutch]\ncolumn = \"0->3\""),
+            [(
+                "output.pedals.clutch",
+                "one of column and row must be a direction (e.g. x->y) and the other must not be \
+                 (e.g. x or x:y)"
+                    .to_owned()
+            )]
+        );
+    }
+}
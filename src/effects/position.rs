@@ -4,7 +4,7 @@ use chroma::Key;
 use rgb::RGB8;
 
 use crate::{
-    config::{self, Color, NumKeys},
+    config::{self, Color, NumKeys, Problem},
     effects::{EffectImpl, EffectInstance},
     property::ScoreProperty,
 };
@@ -34,6 +34,14 @@ const NUMROW: [Key; 10] = [
     Key::Row0,
 ];
 
+/// The keys that show the numbers from 1 upwards.
+pub(super) fn number_keys(numkeys: NumKeys) -> &'static [Key] {
+    match numkeys {
+        NumKeys::Row => &NUMROW[..],
+        NumKeys::Pad => &NUMPAD[..],
+    }
+}
+
 pub struct PositionEffect {
     property: ScoreProperty,
     color: RGB8,
@@ -42,22 +50,29 @@ pub struct PositionEffect {
 
 impl PositionEffect {
     pub fn new(
-        property: ScoreProperty,
+        property: Option<ScoreProperty>,
         output: &config::Output,
         config: &config::ScoreEffect,
         colors: &HashMap<String, Color>,
-    ) -> Self {
-        let keyboard = config
-            .keyboard
-            .as_ref()
-            .expect("TODO: We shouldn't require a keyboard output for MeterEffect.");
-
-        let color = colors[&output.color].0;
-
-        Self {
-            property,
-            color,
-            numkeys: keyboard.numkeys,
+    ) -> Result<Self, Vec<Problem>> {
+        let mut problems = vec![];
+
+        let color = config::color(colors, &output.color).map_err(|p| problems.push(p));
+
+        if config.keyboard.is_none() {
+            problems.push(Problem::new(
+                "output.keyboard",
+                "score effects need an [effect.output.keyboard] table",
+            ));
+        }
+
+        match (property, color, &config.keyboard) {
+            (Some(property), Ok(color), Some(keyboard)) => Ok(Self {
+                property,
+                color,
+                numkeys: keyboard.numkeys,
+            }),
+            _ => Err(problems),
         }
     }
 }
@@ -65,7 +80,7 @@ impl PositionEffect {
 impl EffectImpl for PositionEffect {
     fn start<'a>(&'a self) -> Box<dyn 'a + EffectInstance> {
         Box::new(PositionEffectInstance {
-            effect: &self,
+            effect: self,
             current: None,
         })
     }
@@ -77,9 +92,9 @@ pub struct PositionEffectInstance<'a> {
 }
 
 impl<'a> EffectInstance for PositionEffectInstance<'a> {
-    fn update(&mut self, datagram: &forza::Horizon4Datagram) {
-        self.current = if datagram.sled.is_race_on != 0 {
-            Some(self.effect.property.query(datagram))
+    fn update(&mut self, telemetry: &forza::Telemetry) {
+        self.current = if telemetry.sled.is_race_on != 0 {
+            self.effect.property.query(telemetry)
         } else {
             None
         };
@@ -92,10 +107,7 @@ impl<'a> EffectInstance for PositionEffectInstance<'a> {
             return;
         };
 
-        let keys = match self.effect.numkeys {
-            NumKeys::Row => &NUMROW[..],
-            NumKeys::Pad => &NUMPAD[..],
-        };
+        let keys = number_keys(self.effect.numkeys);
 
         if current < 0 {
             return;
@@ -110,3 +122,75 @@ impl<'a> EffectInstance for PositionEffectInstance<'a> {
         state.set_key(keys[current], self.effect.color);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chroma::Key;
+
+    use crate::testing::{self, BLACK, WHITE};
+
+    fn position(numkeys: &str) -> String {
+        format!(
+            r#"
+            [[effect]]
+            [effect.input]
+            property = "position"
+            [effect.output]
+            type = "score"
+            color = "white"
+            [effect.output.keyboard]
+            numkeys = "{}"
+            "#,
+            numkeys
+        )
+    }
+
+    fn in_position(position: u8) -> forza::Telemetry {
+        let mut telemetry = testing::telemetry(1000.0);
+        telemetry.dash.as_mut().unwrap().race_position = position;
+        telemetry
+    }
+
+    #[test]
+    fn the_position_is_shown_on_its_number_key() {
+        let frame = testing::render(&position("row"), &in_position(3));
+
+        assert_eq!(frame.key(Key::Row3), WHITE);
+        for key in [Key::Row1, Key::Row2, Key::Row4, Key::Row0, Key::Numpad3] {
+            assert_eq!(frame.key(key), BLACK);
+        }
+    }
+
+    #[test]
+    fn tenth_place_is_shown_on_zero() {
+        let frame = testing::render(&position("row"), &in_position(10));
+
+        assert_eq!(frame.key(Key::Row0), WHITE);
+    }
+
+    #[test]
+    fn the_numpad_can_show_the_position() {
+        let frame = testing::render(&position("pad"), &in_position(7));
+
+        assert_eq!(frame.key(Key::Numpad7), WHITE);
+        assert_eq!(frame.key(Key::Row7), BLACK);
+    }
+
+    #[test]
+    fn positions_without_a_key_arent_shown() {
+        // Outside of a race the position is 0, and there are no more keys after 10th or 9th.
+        for (numkeys, place) in [("row", 0), ("row", 11), ("pad", 0), ("pad", 10)] {
+            let frame = testing::render(&position(numkeys), &in_position(place));
+
+            for row in 0..chroma::MAX_ROW {
+                assert_eq!(
+                    testing::row(&frame, row, 0..chroma::MAX_COLUMN),
+                    vec![BLACK; chroma::MAX_COLUMN as usize],
+                    "{} position {}",
+                    numkeys,
+                    place
+                );
+            }
+        }
+    }
+}
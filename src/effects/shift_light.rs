@@ -0,0 +1,323 @@
+use std::{collections::HashMap, time::Instant};
+
+use rgb::RGB8;
+
+use crate::{
+    config::{self, Color, Problem},
+    effects::{EffectImpl, EffectInstance, MeterLayout},
+};
+
+/// Where the shift light starts and where it flashes, as fractions of the RPM range.
+#[derive(Copy, Clone)]
+struct ShiftPoints {
+    start: f32,
+    redline: f32,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Car {
+    Ordinal(i32),
+    Class(i32),
+}
+
+/// Lights up keys one after the other as the engine revs towards the redline, and flashes them all
+/// once it gets there.
+pub struct ShiftLightEffect {
+    color: RGB8,
+    redline_color: RGB8,
+    flash_rate: f32,
+    points: ShiftPoints,
+    /// Overrides of the shift points, with the ones for particular cars first.
+    cars: Vec<(Car, ShiftPoints)>,
+    layout: MeterLayout,
+}
+
+impl ShiftLightEffect {
+    pub fn new(
+        output: &config::Output,
+        config: &config::ShiftLightEffect,
+        colors: &HashMap<String, Color>,
+    ) -> Result<Self, Vec<Problem>> {
+        let mut problems = vec![];
+
+        let color = config::color(colors, &output.color).map_err(|p| problems.push(p));
+        let redline_color = match &config.redline_color {
+            Some(name) => config::color(colors, name).map_err(|p| {
+                problems.push(Problem::new("output.redline_color", p.message));
+            }),
+            None => color,
+        };
+
+        if !(config.flash_rate > 0.0 && config.flash_rate.is_finite()) {
+            problems.push(Problem::new(
+                "output.flash_rate",
+                "the flash rate has to be positive",
+            ));
+        }
+
+        let points = ShiftPoints {
+            start: config.start,
+            redline: config.redline,
+        };
+        if let Err(message) = points.check() {
+            problems.push(Problem::new("output.redline", message));
+        }
+
+        let mut cars = vec![];
+        for car in &config.car {
+            let matching = match (car.ordinal, car.class) {
+                (Some(ordinal), None) => Car::Ordinal(ordinal),
+                (None, Some(class)) => Car::Class(class),
+                _ => {
+                    problems.push(Problem::new(
+                        "output.car",
+                        "each car needs either an ordinal or a class, but not both",
+                    ));
+                    continue;
+                }
+            };
+
+            let car_points = ShiftPoints {
+                start: car.start.unwrap_or(points.start),
+                redline: car.redline.unwrap_or(points.redline),
+            };
+            if let Err(message) = car_points.check() {
+                problems.push(Problem::new("output.car", message));
+            }
+
+            cars.push((matching, car_points));
+        }
+        // Settings for a particular car win over those for its class.
+        cars.sort_by_key(|(car, _)| matches!(car, Car::Class(_)));
+
+        let layout = MeterLayout::new(config.keyboard.as_ref(), "shift-light")
+            .map_err(|p| problems.extend(p));
+
+        match (color, redline_color, layout) {
+            (Ok(color), Ok(redline_color), Ok(layout)) if problems.is_empty() => Ok(Self {
+                color,
+                redline_color,
+                flash_rate: config.flash_rate,
+                points,
+                cars,
+                layout,
+            }),
+            _ => Err(problems),
+        }
+    }
+
+    fn points(&self, sled: &forza::Sled) -> ShiftPoints {
+        self.cars
+            .iter()
+            .find(|(car, _)| {
+                *car == Car::Ordinal(sled.car_ordinal) || *car == Car::Class(sled.car_class)
+            })
+            .map_or(self.points, |&(_, points)| points)
+    }
+}
+
+impl ShiftPoints {
+    fn check(&self) -> Result<(), String> {
+        if 0.0 <= self.start && self.start < self.redline && self.redline <= 1.0 {
+            Ok(())
+        } else {
+            Err(format!(
+                "start ({}) has to be less than redline ({}), and both between 0 and 1",
+                self.start, self.redline
+            ))
+        }
+    }
+}
+
+impl EffectImpl for ShiftLightEffect {
+    fn start<'a>(&'a self) -> Box<dyn 'a + EffectInstance> {
+        Box::new(ShiftLightEffectInstance {
+            effect: self,
+            current: None,
+            redline_since: None,
+        })
+    }
+}
+
+pub struct ShiftLightEffectInstance<'a> {
+    effect: &'a ShiftLightEffect,
+    /// The fraction of the RPM range, and the shift points for the car.
+    current: Option<(f32, ShiftPoints)>,
+    /// When the engine hit the redline, which the flashing is timed from.
+    redline_since: Option<Instant>,
+}
+
+impl<'a> EffectInstance for ShiftLightEffectInstance<'a> {
+    fn update(&mut self, telemetry: &forza::Telemetry) {
+        let sled = &telemetry.sled;
+        let range = sled.engine_max_rpm - sled.engine_idle_rpm;
+
+        self.current = if sled.is_race_on != 0 && range > 0.0 {
+            let rpm = (sled.current_engine_rpm - sled.engine_idle_rpm) / range;
+            Some((rpm, self.effect.points(sled)))
+        } else {
+            None
+        };
+    }
+
+    fn tick(&mut self, tick: &super::prelude::Tick, state: &mut super::prelude::ChromaState) {
+        let (rpm, points) = match self.current {
+            Some(current) => current,
+            None => {
+                self.redline_since = None;
+                return;
+            }
+        };
+
+        let layout = &self.effect.layout;
+        let length = layout.length();
+
+        if rpm >= points.redline {
+            let since = *self.redline_since.get_or_insert(tick.now);
+            let flashes = (tick.now - since).as_secs_f32() * self.effect.flash_rate;
+            // Each flash is on for the first half and off for the second.
+            if flashes % 1.0 < 0.5 {
+                for step in 0..length {
+                    for (row, column) in layout.positions(step) {
+                        state.set_position(row, column, self.effect.redline_color);
+                    }
+                }
+            }
+            return;
+        }
+
+        self.redline_since = None;
+        if rpm <= points.start {
+            return;
+        }
+
+        let progress = (rpm - points.start) / (points.redline - points.start);
+        let lit = ((progress * length as f32).ceil() as u8).min(length);
+        for step in 0..lit {
+            for (row, column) in layout.positions(step) {
+                state.set_position(row, column, self.effect.color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::testing::{self, BLACK, RED, WHITE};
+
+    /// A shift light along the first ten keys of the second row, from half of the RPM range to
+    /// 90%, that flashes red twice a second.
+    fn shift_light(cars: &str) -> String {
+        format!(
+            r#"
+            [[effect]]
+            [effect.output]
+            type = "shift-light"
+            color = "white"
+            redline_color = "red"
+            start = 0.5
+            redline = 0.9
+            flash_rate = 2.0
+            [effect.output.keyboard]
+            column = "0->9"
+            row = 1
+            {}
+            "#,
+            cars
+        )
+    }
+
+    /// The rpm at `fraction` of the range from idle to the engine's maximum.
+    fn at(fraction: f32) -> forza::Telemetry {
+        testing::telemetry(1000.0 + fraction * 7000.0)
+    }
+
+    fn lit(keys: usize, color: rgb::RGB8) -> Vec<rgb::RGB8> {
+        let mut strip = vec![BLACK; 10];
+        strip[..keys].fill(color);
+        strip
+    }
+
+    #[test]
+    fn keys_light_up_between_the_start_and_the_redline() {
+        let config = shift_light("");
+
+        // 0%, 25% and 75% of the way from the start to the redline.
+        for (fraction, keys) in [(0.3, 0), (0.5, 0), (0.6, 3), (0.8, 8)] {
+            let frame = testing::render(&config, &at(fraction));
+            assert_eq!(
+                testing::row(&frame, 1, 0..11),
+                [lit(keys, WHITE), vec![BLACK]].concat(),
+                "at {}",
+                fraction
+            );
+        }
+    }
+
+    #[test]
+    fn the_redline_flashes_at_the_flash_rate() {
+        let driver = testing::driver(&shift_light(""));
+        let mut session = driver.start();
+        let start = Instant::now();
+        let after = |millis| start + Duration::from_millis(millis);
+
+        // Each flash lasts half a second, and is on for the first half of it.
+        for (millis, on) in [
+            (0, true),
+            (200, true),
+            (300, false),
+            (450, false),
+            (550, true),
+        ] {
+            let frame = testing::show(&mut session, &at(0.95), after(millis));
+            let expected = if on { RED } else { BLACK };
+            assert_eq!(
+                testing::row(&frame, 1, 0..10),
+                vec![expected; 10],
+                "at {}ms",
+                millis
+            );
+        }
+
+        // Dropping below the redline starts the next flash over.
+        let frame = testing::show(&mut session, &at(0.8), after(600));
+        assert_eq!(testing::row(&frame, 1, 0..10), lit(8, WHITE));
+        let frame = testing::show(&mut session, &at(0.95), after(800));
+        assert_eq!(testing::row(&frame, 1, 0..10), vec![RED; 10]);
+    }
+
+    #[test]
+    fn cars_take_precedence_over_classes_and_classes_over_the_defaults() {
+        let config = shift_light(
+            r#"
+            [[effect.output.car]]
+            class = 3
+            redline = 0.6
+            [[effect.output.car]]
+            ordinal = 42
+            start = 0.6
+            redline = 0.7
+            "#,
+        );
+        let car = |ordinal, class| {
+            let mut telemetry = at(0.65);
+            telemetry.sled.car_ordinal = ordinal;
+            telemetry.sled.car_class = class;
+            telemetry
+        };
+
+        // The defaults: 37.5% of the way to the redline.
+        let frame = testing::render(&config, &car(7, 2));
+        assert_eq!(testing::row(&frame, 1, 0..10), lit(4, WHITE));
+
+        // The class: past the redline.
+        let frame = testing::render(&config, &car(7, 3));
+        assert_eq!(testing::row(&frame, 1, 0..10), vec![RED; 10]);
+
+        // The car, even though it's in that class: half way to the redline.
+        let frame = testing::render(&config, &car(42, 3));
+        assert_eq!(testing::row(&frame, 1, 0..10), lit(5, WHITE));
+    }
+}
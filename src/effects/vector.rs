@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use rgb::RGB8;
+
+use crate::{
+    config::{self, Color, Problem},
+    effects::{fade, EffectImpl, EffectInstance, Region},
+    property::VectorProperty,
+};
+
+/// Moves a dot around a block of keys, e.g. pushed forwards by braking and sideways by cornering
+/// for the g-force. The middle of the block is no force at all.
+pub struct VectorEffect {
+    property: VectorProperty,
+    color: RGB8,
+    radius: f32,
+    region: Region,
+}
+
+impl VectorEffect {
+    pub fn new(
+        property: Option<VectorProperty>,
+        output: &config::Output,
+        config: &config::VectorEffect,
+        colors: &HashMap<String, Color>,
+    ) -> Result<Self, Vec<Problem>> {
+        let mut problems = vec![];
+
+        let color = config::color(colors, &output.color).map_err(|p| problems.push(p));
+
+        if !(config.radius >= 0.0 && config.radius.is_finite()) {
+            problems.push(Problem::new(
+                "output.radius",
+                "the radius can't be negative",
+            ));
+        }
+
+        let region = match &config.keyboard {
+            Some(keyboard) => Some(Region::from_config(
+                ("output.keyboard.column", "output.keyboard.row"),
+                keyboard,
+                "vector",
+                &mut problems,
+            )),
+            None => {
+                problems.push(Problem::new(
+                    "output.keyboard",
+                    "vector effects need an [effect.output.keyboard] table",
+                ));
+                None
+            }
+        };
+
+        match (property, color, region) {
+            (Some(property), Ok(color), Some(region)) if problems.is_empty() => Ok(Self {
+                property,
+                color,
+                radius: config.radius,
+                region,
+            }),
+            _ => Err(problems),
+        }
+    }
+}
+
+impl EffectImpl for VectorEffect {
+    fn start<'a>(&'a self) -> Box<dyn 'a + EffectInstance> {
+        Box::new(VectorEffectInstance {
+            effect: self,
+            current: None,
+        })
+    }
+}
+
+pub struct VectorEffectInstance<'a> {
+    effect: &'a VectorEffect,
+    current: Option<(f32, f32)>,
+}
+
+impl<'a> EffectInstance for VectorEffectInstance<'a> {
+    fn update(&mut self, telemetry: &forza::Telemetry) {
+        self.current = if telemetry.sled.is_race_on != 0 {
+            self.effect.property.query(telemetry)
+        } else {
+            None
+        };
+    }
+
+    fn tick(&mut self, _tick: &super::prelude::Tick, state: &mut super::prelude::ChromaState) {
+        let (right, forwards) = match self.current {
+            Some(current) => current,
+            None => return,
+        };
+
+        // Where the dot is, in keys. The top row is the front of the car.
+        let region = &self.effect.region;
+        let (left, width) = (*region.columns.start(), region.columns.len() - 1);
+        let (top, height) = (*region.rows.start(), region.rows.len() - 1);
+        let column = left as f32 + (1.0 + right) / 2.0 * width as f32;
+        let row = top as f32 + (1.0 - forwards) / 2.0 * height as f32;
+
+        let radius = self.effect.radius;
+        if radius == 0.0 {
+            state.set_position(row.round() as u8, column.round() as u8, self.effect.color);
+            return;
+        }
+
+        for key_row in region.rows.clone() {
+            for key_column in region.columns.clone() {
+                let distance = (key_row as f32 - row).hypot(key_column as f32 - column);
+                if distance > radius {
+                    continue;
+                }
+
+                let brightness = 1.0 - distance / (radius + 1.0);
+                state.set_position(key_row, key_column, fade(self.effect.color, brightness));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rgb::RGB8;
+
+    use crate::testing::{self, BLACK, WHITE};
+
+    /// The g-force on a 5×5 block in the top left, full scale at 10 m/s².
+    fn vector(radius: f32) -> String {
+        format!(
+            r#"
+            [[effect]]
+            [effect.input]
+            property = "g-force"
+            max_value = 10.0
+            [effect.output]
+            type = "vector"
+            color = "white"
+            radius = {}
+            [effect.output.keyboard]
+            column = "0:4"
+            row = "0:4"
+            "#,
+            radius
+        )
+    }
+
+    /// The car accelerating by `x` to the right and `z` forwards.
+    fn accelerating(x: f32, z: f32) -> forza::Telemetry {
+        let mut telemetry = testing::telemetry(1000.0);
+        telemetry.sled.acceleration.x = x;
+        telemetry.sled.acceleration.z = z;
+        telemetry
+    }
+
+    /// The `(row, column)` of every lit key.
+    fn lit(frame: &chroma::Frame) -> Vec<(u8, u8)> {
+        let mut lit = vec![];
+        for row in 0..chroma::MAX_ROW {
+            for column in 0..chroma::MAX_COLUMN {
+                if frame.position(row, column) != BLACK {
+                    lit.push((row, column));
+                }
+            }
+        }
+        lit
+    }
+
+    #[test]
+    fn no_force_is_the_middle() {
+        let frame = testing::render(&vector(0.0), &accelerating(0.0, 0.0));
+
+        assert_eq!(lit(&frame), [(2, 2)]);
+        assert_eq!(frame.position(2, 2), WHITE);
+    }
+
+    #[test]
+    fn the_dot_moves_with_the_force_on_the_driver() {
+        for (x, z, key, situation) in [
+            (-10.0, 0.0, (2, 4), "turning left pushes the driver right"),
+            (10.0, 0.0, (2, 0), "turning right pushes the driver left"),
+            (0.0, -10.0, (0, 2), "braking pushes the driver forwards"),
+            (0.0, 10.0, (4, 2), "accelerating pushes the driver back"),
+            (-5.0, 0.0, (2, 3), "half of the maximum is half way out"),
+            (
+                -20.0,
+                0.0,
+                (2, 4),
+                "forces past the maximum stay at the edge",
+            ),
+        ] {
+            let frame = testing::render(&vector(0.0), &accelerating(x, z));
+            assert_eq!(lit(&frame), [key], "{}", situation);
+        }
+    }
+
+    #[test]
+    fn diagonal_forces_move_the_dot_into_the_corners() {
+        for (x, z, key) in [
+            (-10.0, -10.0, (0, 4)),
+            (10.0, -10.0, (0, 0)),
+            (-10.0, 10.0, (4, 4)),
+            (10.0, 10.0, (4, 0)),
+        ] {
+            let frame = testing::render(&vector(0.0), &accelerating(x, z));
+            assert_eq!(lit(&frame), [key], "accelerating by ({}, {})", x, z);
+        }
+    }
+
+    #[test]
+    fn keys_within_the_radius_fade_with_the_distance() {
+        let frame = testing::render(&vector(1.0), &accelerating(0.0, 0.0));
+
+        assert_eq!(lit(&frame), [(1, 2), (2, 1), (2, 2), (2, 3), (3, 2)]);
+        assert_eq!(frame.position(2, 2), WHITE);
+        assert_eq!(frame.position(1, 2), RGB8::new(127, 127, 127));
+    }
+}
@@ -0,0 +1,455 @@
+use std::{fmt, iter::Peekable, str::CharIndices};
+
+use forza::Telemetry;
+
+use crate::field::{self, Field};
+
+/// An arithmetic expression over telemetry fields, e.g. `dash.brake / 255`.
+///
+/// Fields are referenced by their path, e.g. `sled.current_engine_rpm` or
+/// `dash.tire_temp.front_left`. Numbers can be combined with `+`, `-`, `*` and `/`, grouped with
+/// parentheses and passed to `min`, `max`, `abs`, `clamp`, `sqrt` and `hypot`. `hypot` gives the
+/// length of a vector, e.g. `hypot(sled.acceleration.x, sled.acceleration.z)`.
+#[derive(Clone, Debug)]
+pub enum Expression {
+    Number(f32),
+    Field(&'static Field),
+    Negate(Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+    Call(Function, Vec<Expression>),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Function {
+    Min,
+    Max,
+    Abs,
+    Clamp,
+    Sqrt,
+    Hypot,
+}
+
+impl Function {
+    const ALL: [Function; 6] = [
+        Function::Abs,
+        Function::Clamp,
+        Function::Hypot,
+        Function::Max,
+        Function::Min,
+        Function::Sqrt,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Function::Min => "min",
+            Function::Max => "max",
+            Function::Abs => "abs",
+            Function::Clamp => "clamp",
+            Function::Sqrt => "sqrt",
+            Function::Hypot => "hypot",
+        }
+    }
+
+    /// Checks the number of arguments, returning what was expected if it's wrong.
+    fn check_arity(self, count: usize) -> Result<(), &'static str> {
+        match self {
+            Function::Min | Function::Max | Function::Hypot if count < 2 => {
+                Err("at least 2 arguments")
+            }
+            Function::Abs | Function::Sqrt if count != 1 => Err("1 argument"),
+            Function::Clamp if count != 3 => Err("3 arguments"),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser {
+            source,
+            chars: source.char_indices().peekable(),
+        };
+
+        let expression = parser.expression()?;
+        parser.skip_whitespace();
+        match parser.chars.peek() {
+            None => Ok(expression),
+            Some(&(at, c)) => Err(ParseError::new(at, format!("unexpected '{}'", c))),
+        }
+    }
+
+    /// Returns `None` if the telemetry doesn't carry one of the fields, or if the result isn't a
+    /// finite number, e.g. after dividing by zero.
+    pub fn evaluate(&self, telemetry: &Telemetry) -> Option<f32> {
+        let value = match self {
+            Expression::Number(value) => *value,
+            Expression::Field(field) => field.read(telemetry)?,
+            Expression::Negate(operand) => -operand.evaluate(telemetry)?,
+            Expression::Binary(operator, left, right) => {
+                let (left, right) = (left.evaluate(telemetry)?, right.evaluate(telemetry)?);
+                match operator {
+                    Operator::Add => left + right,
+                    Operator::Subtract => left - right,
+                    Operator::Multiply => left * right,
+                    Operator::Divide => left / right,
+                }
+            }
+            Expression::Call(function, arguments) => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| argument.evaluate(telemetry))
+                    .collect::<Option<Vec<_>>>()?;
+                match function {
+                    Function::Min => arguments.into_iter().fold(f32::INFINITY, f32::min),
+                    Function::Max => arguments.into_iter().fold(f32::NEG_INFINITY, f32::max),
+                    Function::Abs => arguments[0].abs(),
+                    // Unlike `f32::clamp`, this doesn't panic if the bounds are the wrong way
+                    // around.
+                    Function::Clamp => arguments[0].max(arguments[1]).min(arguments[2]),
+                    // Negative numbers have no square root, so aren't finite.
+                    Function::Sqrt => arguments[0].sqrt(),
+                    Function::Hypot => arguments.into_iter().fold(0.0, f32::hypot),
+                }
+            }
+        };
+
+        Some(value).filter(|value| value.is_finite())
+    }
+}
+
+/// Why an expression couldn't be parsed.
+#[derive(Clone, Debug)]
+pub struct ParseError {
+    /// The byte offset in the expression where the problem is.
+    pub at: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(at: usize, message: impl Into<String>) -> Self {
+        Self {
+            at,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (at character {})", self.message, self.at + 1)
+    }
+}
+
+/// A recursive descent parser, with each method parsing one level of precedence.
+struct Parser<'a> {
+    source: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn expression(&mut self) -> Result<Expression, ParseError> {
+        let mut left = self.term()?;
+        loop {
+            let operator = match self.peek() {
+                Some('+') => Operator::Add,
+                Some('-') => Operator::Subtract,
+                _ => return Ok(left),
+            };
+            self.chars.next();
+            left = Expression::Binary(operator, Box::new(left), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Expression, ParseError> {
+        let mut left = self.unary()?;
+        loop {
+            let operator = match self.peek() {
+                Some('*') => Operator::Multiply,
+                Some('/') => Operator::Divide,
+                _ => return Ok(left),
+            };
+            self.chars.next();
+            left = Expression::Binary(operator, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expression, ParseError> {
+        if self.peek() == Some('-') {
+            self.chars.next();
+            return Ok(Expression::Negate(Box::new(self.unary()?)));
+        }
+
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expression, ParseError> {
+        self.skip_whitespace();
+        let (start, c) = match self.chars.peek() {
+            Some(&next) => next,
+            None => {
+                return Err(ParseError::new(
+                    self.source.len(),
+                    "expected a number, field or function",
+                ))
+            }
+        };
+
+        if c == '(' {
+            self.chars.next();
+            let expression = self.expression()?;
+            self.expect(')')?;
+            Ok(expression)
+        } else if c.is_ascii_digit() || c == '.' {
+            let number = self.take_while(start, |c| c.is_ascii_digit() || c == '.');
+            number
+                .parse()
+                .map(Expression::Number)
+                .map_err(|_| ParseError::new(start, format!("invalid number '{}'", number)))
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let name =
+                self.take_while(start, |c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+            if self.peek() == Some('(') {
+                self.chars.next();
+                self.call(start, name)
+            } else {
+                field::get(name)
+                    .map(Expression::Field)
+                    .ok_or_else(|| ParseError::new(start, format!("unknown field '{}'", name)))
+            }
+        } else {
+            Err(ParseError::new(start, format!("unexpected '{}'", c)))
+        }
+    }
+
+    /// Parses the arguments of a call, after the opening parenthesis.
+    fn call(&mut self, start: usize, name: &str) -> Result<Expression, ParseError> {
+        let function = Function::ALL
+            .iter()
+            .copied()
+            .find(|function| function.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = Function::ALL.iter().map(|f| f.name()).collect();
+                ParseError::new(
+                    start,
+                    format!(
+                        "unknown function '{}' - expected one of {}",
+                        name,
+                        names.join(", ")
+                    ),
+                )
+            })?;
+
+        let mut arguments = vec![];
+        if self.peek() == Some(')') {
+            self.chars.next();
+        } else {
+            loop {
+                arguments.push(self.expression()?);
+                match self.peek() {
+                    Some(',') => {
+                        self.chars.next();
+                    }
+                    _ => {
+                        self.expect(')')?;
+                        break;
+                    }
+                }
+            }
+        }
+
+        function
+            .check_arity(arguments.len())
+            .map_err(|expected| ParseError::new(start, format!("'{}' takes {}", name, expected)))?;
+        Ok(Expression::Call(function, arguments))
+    }
+
+    /// Skips whitespace and returns the next character without consuming it.
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.peek().map(|&(_, c)| c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            Some((at, c)) => Err(ParseError::new(
+                at,
+                format!("expected '{}', found '{}'", expected, c),
+            )),
+            None => Err(ParseError::new(
+                self.source.len(),
+                format!("expected '{}'", expected),
+            )),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|&(_, c)| c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn take_while(&mut self, start: usize, predicate: impl Fn(char) -> bool) -> &'a str {
+        let mut end = start;
+        while let Some(&(at, c)) = self.chars.peek() {
+            if !predicate(c) {
+                break;
+            }
+            end = at + c.len_utf8();
+            self.chars.next();
+        }
+        &self.source[start..end]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn evaluate(source: &str) -> Option<f32> {
+        Expression::parse(source)
+            .unwrap()
+            .evaluate(&testing::telemetry(4000.0))
+    }
+
+    fn error(source: &str) -> (usize, String) {
+        let error = Expression::parse(source).unwrap_err();
+        (error.at, error.message)
+    }
+
+    #[test]
+    fn operators_have_precedence() {
+        assert_eq!(evaluate("2 + 3 * 4"), Some(14.0));
+        assert_eq!(evaluate("2 * 3 + 4"), Some(10.0));
+        assert_eq!(evaluate("10 - 6 / 2"), Some(7.0));
+        assert_eq!(evaluate("(2 + 3) * 4"), Some(20.0));
+    }
+
+    #[test]
+    fn operators_are_left_associative() {
+        assert_eq!(evaluate("10 - 4 - 3"), Some(3.0));
+        assert_eq!(evaluate("12 / 3 * 2"), Some(8.0));
+        assert_eq!(evaluate("12 / 3 / 2"), Some(2.0));
+        assert_eq!(evaluate("1 - 2 + 3"), Some(2.0));
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eq!(evaluate("-2"), Some(-2.0));
+        assert_eq!(evaluate("--2"), Some(2.0));
+        assert_eq!(evaluate("2 - -3"), Some(5.0));
+        assert_eq!(evaluate("-2 * 3"), Some(-6.0));
+        assert_eq!(evaluate("-(1 + 2) * 2"), Some(-6.0));
+        assert_eq!(evaluate("-abs(-3)"), Some(-3.0));
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(evaluate("min(3, 1, 2)"), Some(1.0));
+        assert_eq!(evaluate("max(3, 1, 2)"), Some(3.0));
+        assert_eq!(evaluate("abs(1 - 3)"), Some(2.0));
+        assert_eq!(evaluate("clamp(5, 0, 1)"), Some(1.0));
+        assert_eq!(evaluate("clamp(-5, 0, 1)"), Some(0.0));
+        assert_eq!(evaluate("clamp(0.5, 0, 1)"), Some(0.5));
+        assert_eq!(evaluate("sqrt(16)"), Some(4.0));
+        assert_eq!(evaluate("sqrt(-1)"), None);
+        assert_eq!(evaluate("hypot(3, 4)"), Some(5.0));
+        assert_eq!(evaluate("hypot(-3, 4)"), Some(5.0));
+        assert_eq!(evaluate("hypot(2, 3, 6)"), Some(7.0));
+    }
+
+    #[test]
+    fn functions_check_their_argument_counts() {
+        let cases = [
+            ("min()", "'min' takes at least 2 arguments"),
+            ("min(1)", "'min' takes at least 2 arguments"),
+            ("max(1)", "'max' takes at least 2 arguments"),
+            ("abs()", "'abs' takes 1 argument"),
+            ("abs(1, 2)", "'abs' takes 1 argument"),
+            ("clamp(1, 2)", "'clamp' takes 3 arguments"),
+            ("clamp(1, 2, 3, 4)", "'clamp' takes 3 arguments"),
+            ("sqrt()", "'sqrt' takes 1 argument"),
+            ("sqrt(1, 2)", "'sqrt' takes 1 argument"),
+            ("hypot(1)", "'hypot' takes at least 2 arguments"),
+        ];
+        for (source, message) in cases {
+            assert_eq!(error(&format!("1 + {}", source)), (4, message.to_owned()));
+        }
+    }
+
+    #[test]
+    fn fields_are_read_from_the_telemetry() {
+        assert_eq!(evaluate("sled.current_engine_rpm / 2"), Some(2000.0));
+        assert_eq!(
+            evaluate("sled.current_engine_rpm - sled.engine_idle_rpm"),
+            Some(3000.0)
+        );
+
+        // Forza Motorsport 7's sled format doesn't have a dash.
+        let mut telemetry = testing::telemetry(4000.0);
+        telemetry.dash = None;
+        let expression = Expression::parse("sled.current_engine_rpm + dash.speed").unwrap();
+        assert_eq!(expression.evaluate(&telemetry), None);
+    }
+
+    #[test]
+    fn results_have_to_be_finite() {
+        assert_eq!(evaluate("1 / 0"), None);
+        assert_eq!(evaluate("0 / 0"), None);
+        assert_eq!(evaluate("abs(1 / 0)"), None);
+    }
+
+    #[test]
+    fn unknown_fields_and_functions_are_rejected() {
+        assert_eq!(
+            error("2 * sled.nope"),
+            (4, "unknown field 'sled.nope'".to_owned())
+        );
+        // Quads are only fields one tire at a time.
+        assert_eq!(
+            error("dash.tire_temp"),
+            (0, "unknown field 'dash.tire_temp'".to_owned())
+        );
+        assert_eq!(error("rpm"), (0, "unknown field 'rpm'".to_owned()));
+        assert_eq!(
+            error("1 + sqr(2)"),
+            (
+                4,
+                "unknown function 'sqr' - expected one of abs, clamp, hypot, max, min, sqrt"
+                    .to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        assert_eq!(error("1 + * 2"), (4, "unexpected '*'".to_owned()));
+        assert_eq!(error("1 2"), (2, "unexpected '2'".to_owned()));
+        assert_eq!(
+            error("1 +"),
+            (3, "expected a number, field or function".to_owned())
+        );
+        assert_eq!(error("(1 + 2"), (6, "expected ')'".to_owned()));
+        assert_eq!(
+            error("min(1, 2]"),
+            (8, "expected ')', found ']'".to_owned())
+        );
+        assert_eq!(error("1.2.3"), (0, "invalid number '1.2.3'".to_owned()));
+
+        // Positions count characters from 1 when shown.
+        assert_eq!(
+            Expression::parse("1 + * 2").unwrap_err().to_string(),
+            "unexpected '*' (at character 5)"
+        );
+    }
+}
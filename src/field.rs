@@ -0,0 +1,265 @@
+use forza::{Dash, Quad, Sled, Telemetry};
+
+/// A single number in the telemetry, e.g. `sled.tire_slip_angle.front_left`. Paths use the same
+/// names as the datagrams' JSON form.
+#[derive(Debug)]
+pub struct Field {
+    pub path: &'static str,
+    /// Empty for fields without a unit, like ratios and flags.
+    pub unit: &'static str,
+    /// What a meter shows as full unless the config says otherwise, for fields with a natural
+    /// maximum. Fields that can be negative have none, as a meter only shows 0 up to its maximum;
+    /// they need an expression like `abs(dash.steer)` along with a `max_value`.
+    pub max: Option<f32>,
+    read: fn(&Telemetry) -> Option<f32>,
+}
+
+impl Field {
+    /// Returns `None` if the telemetry doesn't carry the field.
+    pub fn read(&self, telemetry: &Telemetry) -> Option<f32> {
+        (self.read)(telemetry)
+    }
+}
+
+pub fn get(path: &str) -> Option<&'static Field> {
+    FIELDS.iter().find(|field| field.path == path)
+}
+
+/// The four fields of a quad, e.g. `dash.tire_temp`, in the order of `forza::Quad`.
+pub fn quad(path: &str) -> Option<Quad<&'static Field>> {
+    let part = |part| get(&format!("{}.{}", path, part));
+    Some(Quad {
+        front_left: part("front_left")?,
+        front_right: part("front_right")?,
+        rear_left: part("rear_left")?,
+        rear_right: part("rear_right")?,
+    })
+}
+
+/// The path of every quad, e.g. `dash.tire_temp`.
+pub fn quads() -> impl Iterator<Item = &'static str> {
+    FIELDS
+        .iter()
+        .filter_map(|field| field.path.strip_suffix(".front_left"))
+}
+
+/// Every field, in the order they appear in the datagrams.
+pub fn all() -> &'static [Field] {
+    &FIELDS
+}
+
+fn sled(telemetry: &Telemetry) -> Option<Sled> {
+    Some(telemetry.sled)
+}
+
+fn dash(telemetry: &Telemetry) -> Option<Dash> {
+    telemetry.dash
+}
+
+/// Adds the fields of a `Sled` or `Dash`, read through the function of the same name, to `fields`.
+/// Vectors and quads are split into their components, which share the unit and maximum.
+macro_rules! fields {
+    ($fields:ident, $source:ident {
+        $($name:ident: $kind:ident($unit:literal $(, $max:expr)?)),* $(,)?
+    }) => {
+        $(fields!(@$kind $fields, $source, $name, $unit, fields!(@max $($max)?));)*
+    };
+    (@max) => { None };
+    (@max $max:expr) => { Some($max) };
+    (@scalar $fields:ident, $source:ident, $name:ident, $unit:literal, $max:expr) => {
+        $fields.extend([Field {
+            path: concat!(stringify!($source), ".", stringify!($name)),
+            unit: $unit,
+            max: $max,
+            read: |telemetry| Some($source(telemetry)?.$name as f32),
+        }]);
+    };
+    (@vector $fields:ident, $source:ident, $name:ident, $unit:literal, $max:expr) => {
+        $fields.extend([
+            fields!(@part $source, $name, x, $unit, $max),
+            fields!(@part $source, $name, y, $unit, $max),
+            fields!(@part $source, $name, z, $unit, $max),
+        ]);
+    };
+    (@quad $fields:ident, $source:ident, $name:ident, $unit:literal, $max:expr) => {
+        $fields.extend([
+            fields!(@part $source, $name, front_left, $unit, $max),
+            fields!(@part $source, $name, front_right, $unit, $max),
+            fields!(@part $source, $name, rear_left, $unit, $max),
+            fields!(@part $source, $name, rear_right, $unit, $max),
+        ]);
+    };
+    (@part $source:ident, $name:ident, $part:ident, $unit:literal, $max:expr) => {
+        Field {
+            path: concat!(stringify!($source), ".", stringify!($name), ".", stringify!($part)),
+            unit: $unit,
+            max: $max,
+            read: |telemetry| Some($source(telemetry)?.$name.$part as f32),
+        }
+    };
+}
+
+lazy_static::lazy_static! {
+    static ref FIELDS: Vec<Field> = fields();
+}
+
+fn fields() -> Vec<Field> {
+    let mut fields = Vec::new();
+
+    fields!(
+        fields,
+        sled {
+            is_race_on: scalar("", 1.0),
+            timestamp_ms: scalar("ms"),
+            engine_max_rpm: scalar("rpm"),
+            engine_idle_rpm: scalar("rpm"),
+            current_engine_rpm: scalar("rpm"),
+            acceleration: vector("m/s²"),
+            velocity: vector("m/s"),
+            angular_velocity: vector("rad/s"),
+            yaw: scalar("rad"),
+            pitch: scalar("rad"),
+            roll: scalar("rad"),
+            // 0 is fully stretched and 1 is fully compressed.
+            normalized_suspension_travel: quad("", 1.0),
+            // The slip ratio. Like the slip angle it's signed, unlike the combined slip, and grip is
+            // lost once any of the three pass 1 either way.
+            tire_split_ratio: quad(""),
+            wheel_rotation_speed: quad("rad/s"),
+            wheel_on_rumble_strip: quad("", 1.0),
+            wheel_in_puddle_depth: quad("", 1.0),
+            surface_rumble: quad(""),
+            tire_slip_angle: quad(""),
+            tire_combined_slip: quad("", 1.0),
+            suspension_travel_meters: quad("m"),
+            car_ordinal: scalar(""),
+            // D, C, B, A, S1, S2 and X.
+            car_class: scalar("", 6.0),
+            car_performance_index: scalar("", 999.0),
+            // Front, rear and all wheel drive.
+            drivetrain_type: scalar("", 2.0),
+            num_cylinders: scalar(""),
+        }
+    );
+
+    fields!(
+        fields,
+        dash {
+            position: vector("m"),
+            speed: scalar("m/s"),
+            power: scalar("W"),
+            torque: scalar("N·m"),
+            tire_temp: quad("°F"),
+            boost: scalar("psi"),
+            fuel: scalar("", 1.0),
+            distance_traveled: scalar("m"),
+            best_lap: scalar("s"),
+            last_lap: scalar("s"),
+            current_lap: scalar("s"),
+            current_race_time: scalar("s"),
+            lap_number: scalar(""),
+            race_position: scalar(""),
+            accel: scalar("", 255.0),
+            brake: scalar("", 255.0),
+            clutch: scalar("", 255.0),
+            hand_brake: scalar("", 255.0),
+            gear: scalar(""),
+            // -127 is full left and 127 full right.
+            steer: scalar(""),
+            normalized_driving_line: scalar(""),
+            normalized_ai_brake_difference: scalar(""),
+        }
+    );
+
+    // Only Forza Motorsport (2023) reports tire wear, which comes after the dash.
+    fields.extend([
+        Field {
+            path: "tire_wear.front_left",
+            unit: "",
+            max: Some(1.0),
+            read: |telemetry| Some(telemetry.tire_wear?.front_left),
+        },
+        Field {
+            path: "tire_wear.front_right",
+            unit: "",
+            max: Some(1.0),
+            read: |telemetry| Some(telemetry.tire_wear?.front_right),
+        },
+        Field {
+            path: "tire_wear.rear_left",
+            unit: "",
+            max: Some(1.0),
+            read: |telemetry| Some(telemetry.tire_wear?.rear_left),
+        },
+        Field {
+            path: "tire_wear.rear_right",
+            unit: "",
+            max: Some(1.0),
+            read: |telemetry| Some(telemetry.tire_wear?.rear_right),
+        },
+    ]);
+
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use forza::{Dash, Quad, Sled};
+    use serde_json::{json, Value};
+
+    use crate::testing;
+
+    /// Numbers every leaf of `value` from `next` on, returning the path of each leaf.
+    fn number_leaves(path: &str, value: &mut Value, next: &mut u8) -> Vec<String> {
+        match value {
+            Value::Object(object) => object
+                .iter_mut()
+                .flat_map(|(name, value)| {
+                    let path = match path {
+                        "" => name.clone(),
+                        path => format!("{}.{}", path, name),
+                    };
+                    number_leaves(&path, value, next)
+                })
+                .collect(),
+            value => {
+                *value = Value::from(*next);
+                *next += 1;
+                vec![path.to_owned()]
+            }
+        }
+    }
+
+    /// Fields are listed by hand, so check them against the names the datagrams serialize with,
+    /// and that each reads the value at its own path.
+    #[test]
+    fn fields_match_the_datagrams() {
+        let mut datagram = json!({
+            "sled": Sled::default(),
+            "dash": Dash::default(),
+            "tire_wear": Quad::<f32>::default(),
+        });
+        let mut expected = number_leaves("", &mut datagram, &mut 1);
+        expected.sort();
+
+        let mut paths: Vec<_> = super::all().iter().map(|field| field.path).collect();
+        paths.sort_unstable();
+        assert_eq!(paths, expected);
+
+        let mut telemetry = testing::telemetry(0.0);
+        telemetry.sled = serde_json::from_value(datagram["sled"].clone()).unwrap();
+        telemetry.dash = Some(serde_json::from_value(datagram["dash"].clone()).unwrap());
+        telemetry.tire_wear = Some(serde_json::from_value(datagram["tire_wear"].clone()).unwrap());
+        for field in super::all() {
+            let expected = datagram
+                .pointer(&format!("/{}", field.path.replace('.', "/")))
+                .and_then(Value::as_f64);
+            assert_eq!(
+                field.read(&telemetry),
+                expected.map(|value| value as f32),
+                "{}",
+                field.path
+            );
+        }
+    }
+}
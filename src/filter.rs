@@ -0,0 +1,311 @@
+use std::time::Duration;
+
+use crate::config::{self, Curve, Problem};
+
+/// Shapes a rate property's value before it's shown. The steps are applied in the order of the
+/// fields, with the value being a fraction of the property's maximum throughout.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    remap: Option<(f32, f32)>,
+    deadzone: f32,
+    curve: Option<Curve>,
+    invert: bool,
+    /// The time constant of an exponential moving average, in seconds.
+    smoothing: Option<f32>,
+    /// In fractions per second.
+    slew_rate: Option<f32>,
+}
+
+impl Filter {
+    pub fn from_config(config: &config::Input) -> Result<Self, Vec<Problem>> {
+        let mut problems = vec![];
+
+        if let Some([min, max]) = config.remap {
+            if !(min < max && min.is_finite() && max.is_finite()) {
+                problems.push(Problem::new(
+                    "input.remap",
+                    "the first value has to be less than the second",
+                ));
+            }
+        }
+        if let Some(deadzone) = config.deadzone {
+            if !(0.0..1.0).contains(&deadzone) {
+                problems.push(Problem::new(
+                    "input.deadzone",
+                    "the deadzone has to be at least 0 and less than 1",
+                ));
+            }
+        }
+        if let Some(Curve::Gamma(gamma)) = config.curve {
+            if !(gamma > 0.0 && gamma.is_finite()) {
+                problems.push(Problem::new("input.curve", "gamma has to be positive"));
+            }
+        }
+        for (key, value) in [
+            ("input.smoothing", config.smoothing),
+            ("input.slew_rate", config.slew_rate),
+        ] {
+            if value.is_some_and(|value| !(value > 0.0 && value.is_finite())) {
+                problems.push(Problem::new(key, "this has to be positive"));
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+
+        Ok(Self {
+            remap: config.remap.map(|[min, max]| (min, max)),
+            deadzone: config.deadzone.unwrap_or(0.0),
+            curve: config.curve,
+            invert: config.invert,
+            smoothing: config.smoothing,
+            slew_rate: config.slew_rate,
+        })
+    }
+
+    /// Whether the filter leaves values as they are.
+    pub fn is_identity(&self) -> bool {
+        self.remap.is_none()
+            && self.deadzone == 0.0
+            && self.curve.is_none()
+            && !self.invert
+            && self.smoothing.is_none()
+            && self.slew_rate.is_none()
+    }
+
+    pub fn start(&self) -> FilterState<'_> {
+        FilterState {
+            filter: self,
+            value: None,
+        }
+    }
+
+    /// Applies the steps that don't depend on time.
+    fn shape(&self, value: f32) -> f32 {
+        let mut value = value;
+
+        if let Some((min, max)) = self.remap {
+            value = (value - min) / (max - min);
+        }
+        value = value.clamp(0.0, 1.0);
+
+        if value < self.deadzone {
+            value = 0.0;
+        } else {
+            value = (value - self.deadzone) / (1.0 - self.deadzone);
+        }
+
+        value = match self.curve {
+            None | Some(Curve::Linear) => value,
+            Some(Curve::Gamma(gamma)) => value.powf(gamma),
+            Some(Curve::Log) => (1.0 + 9.0 * value).log10(),
+        };
+
+        if self.invert {
+            value = 1.0 - value;
+        }
+
+        value
+    }
+}
+
+/// The smoothed value of a filter, which belongs to a running effect.
+pub struct FilterState<'a> {
+    filter: &'a Filter,
+    value: Option<f32>,
+}
+
+impl<'a> FilterState<'a> {
+    /// Moves towards the latest reading, `elapsed` after the previous step. Without a reading the
+    /// filter starts over, and the first reading after that is shown as is.
+    pub fn step(&mut self, reading: Option<f32>, elapsed: Option<Duration>) -> Option<f32> {
+        let target = match reading {
+            Some(reading) => self.filter.shape(reading),
+            None => {
+                self.value = None;
+                return None;
+            }
+        };
+
+        let value = match (self.value, elapsed) {
+            (Some(previous), Some(elapsed)) => {
+                let elapsed = elapsed.as_secs_f32();
+                let mut value = target;
+
+                if let Some(smoothing) = self.filter.smoothing {
+                    let weight = 1.0 - (-elapsed / smoothing).exp();
+                    value = previous + (value - previous) * weight;
+                }
+                if let Some(slew_rate) = self.filter.slew_rate {
+                    let limit = slew_rate * elapsed;
+                    value = previous + (value - previous).clamp(-limit, limit);
+                }
+
+                value
+            }
+            _ => target,
+        };
+
+        self.value = Some(value);
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(config: &str) -> Filter {
+        Filter::from_config(&input(config)).unwrap()
+    }
+
+    fn input(config: &str) -> config::Input {
+        toml::from_str(&format!("property = \"rpm\"\n{}", config)).unwrap()
+    }
+
+    fn shape(config: &str, values: &[f32]) -> Vec<f32> {
+        let filter = filter(config);
+        values.iter().map(|&value| filter.shape(value)).collect()
+    }
+
+    fn assert_close(actual: Option<f32>, expected: f32) {
+        let actual = actual.unwrap();
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    fn seconds(seconds: f32) -> Option<Duration> {
+        Some(Duration::from_secs_f32(seconds))
+    }
+
+    #[test]
+    fn values_are_clamped() {
+        assert_eq!(
+            shape("", &[-0.5, 0.0, 0.5, 1.0, 1.5]),
+            [0.0, 0.0, 0.5, 1.0, 1.0]
+        );
+        assert!(filter("").is_identity());
+    }
+
+    #[test]
+    fn deadzone() {
+        assert_eq!(
+            shape("deadzone = 0.25", &[0.0, 0.1, 0.25, 0.625, 1.0]),
+            [0.0, 0.0, 0.0, 0.5, 1.0]
+        );
+        assert!(!filter("deadzone = 0.2").is_identity());
+    }
+
+    #[test]
+    fn remap() {
+        assert_eq!(
+            shape("remap = [0.25, 0.75]", &[0.0, 0.25, 0.5, 0.75, 0.9]),
+            [0.0, 0.0, 0.5, 1.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn curves() {
+        assert_eq!(
+            shape("curve = \"linear\"", &[0.0, 0.25, 1.0]),
+            [0.0, 0.25, 1.0]
+        );
+        assert_eq!(
+            shape("curve = { gamma = 2.0 }", &[0.0, 0.5, 1.0]),
+            [0.0, 0.25, 1.0]
+        );
+        assert_eq!(
+            shape("curve = { gamma = 0.5 }", &[0.0, 0.25, 1.0]),
+            [0.0, 0.5, 1.0]
+        );
+
+        let log = shape("curve = \"log\"", &[0.0, 0.1, 0.5, 1.0]);
+        assert_eq!(log[0], 0.0);
+        assert_close(Some(log[1]), 1.9f32.log10());
+        assert!(log[2] > 0.5);
+        assert_eq!(log[3], 1.0);
+    }
+
+    #[test]
+    fn invert() {
+        assert_eq!(shape("invert = true", &[0.0, 0.25, 1.0]), [1.0, 0.75, 0.0]);
+        // Inverting comes after the curve and deadzone.
+        assert_eq!(
+            shape("invert = true\ncurve = { gamma = 2.0 }", &[0.5]),
+            [0.75]
+        );
+        assert_eq!(shape("invert = true\ndeadzone = 0.5", &[0.25]), [1.0]);
+    }
+
+    #[test]
+    fn smoothing_is_an_exponential_moving_average() {
+        let filter = filter("smoothing = 1.0");
+        let mut state = filter.start();
+
+        // The first reading is shown as is.
+        assert_eq!(state.step(Some(0.0), seconds(1.0)), Some(0.0));
+        assert_close(state.step(Some(1.0), seconds(1.0)), 1.0 - (-1.0f32).exp());
+
+        // Two half steps get as far as a whole one.
+        let mut halves = filter.start();
+        halves.step(Some(0.0), None);
+        halves.step(Some(1.0), seconds(0.5));
+        assert_close(halves.step(Some(1.0), seconds(0.5)), 1.0 - (-1.0f32).exp());
+
+        // No time passing means no movement, and a long time means getting all the way.
+        let mut state = filter.start();
+        state.step(Some(0.0), None);
+        assert_eq!(state.step(Some(1.0), seconds(0.0)), Some(0.0));
+        assert_close(state.step(Some(1.0), seconds(100.0)), 1.0);
+    }
+
+    #[test]
+    fn slew_rate_limits_movement() {
+        let filter = filter("slew_rate = 0.5");
+        let mut state = filter.start();
+
+        assert_eq!(state.step(Some(0.0), None), Some(0.0));
+        assert_close(state.step(Some(1.0), seconds(0.5)), 0.25);
+        assert_close(state.step(Some(1.0), seconds(1.0)), 0.75);
+        // It doesn't overshoot.
+        assert_close(state.step(Some(1.0), seconds(2.0)), 1.0);
+        // And it limits falling too.
+        assert_close(state.step(Some(0.0), seconds(0.1)), 0.95);
+        assert_close(state.step(Some(0.0), seconds(0.0)), 0.95);
+    }
+
+    #[test]
+    fn missing_readings_start_over() {
+        let filter = filter("smoothing = 1.0\nslew_rate = 0.1");
+        let mut state = filter.start();
+
+        state.step(Some(0.0), None);
+        assert_eq!(state.step(None, seconds(1.0)), None);
+        assert_eq!(state.step(Some(1.0), seconds(1.0)), Some(1.0));
+    }
+
+    #[test]
+    fn invalid_filters_are_problems() {
+        let problems = Filter::from_config(&input(
+            "remap = [0.8, 0.2]\ndeadzone = 1.0\ncurve = { gamma = 0.0 }\nsmoothing = 0.0\n\
+             slew_rate = -1.0",
+        ))
+        .unwrap_err();
+        let keys: Vec<_> = problems.iter().map(|problem| problem.key).collect();
+        assert_eq!(
+            keys,
+            [
+                "input.remap",
+                "input.deadzone",
+                "input.curve",
+                "input.smoothing",
+                "input.slew_rate"
+            ]
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The maxima that `auto_raise` properties have learned, per car and property, kept in a TOML file
+/// between sessions so that meters are calibrated as soon as a car is driven again.
+pub struct LearnedMaxima {
+    path: PathBuf,
+    /// Keyed by the car ordinal and then the property. TOML only has string keys, so the car
+    /// ordinal is kept as one too.
+    maxima: RefCell<BTreeMap<String, BTreeMap<String, f32>>>,
+    changed: Cell<bool>,
+}
+
+impl LearnedMaxima {
+    /// Loads the maxima from `path`, starting without any if the file doesn't exist yet.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let maxima = match fs::read_to_string(path) {
+            Ok(source) => toml::from_str(&source).map_err(|e| {
+                format!(
+                    "{}: {} - fix or delete the file, or pass --reset-learned",
+                    path.display(),
+                    e
+                )
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(format!("{}: {}", path.display(), e).into()),
+        };
+
+        Ok(Self {
+            path: path.to_owned(),
+            maxima: RefCell::new(maxima),
+            changed: Cell::new(false),
+        })
+    }
+
+    /// Forgets everything that was learned, by deleting the file at `path`.
+    pub fn reset(path: impl AsRef<Path>) -> io::Result<()> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn get(&self, car: i32, property: &str) -> Option<f32> {
+        self.maxima
+            .borrow()
+            .get(&car.to_string())?
+            .get(property)
+            .copied()
+    }
+
+    /// Raises what was learned for `car` and `property` to `max`, unless it's already higher.
+    pub fn raise(&self, car: i32, property: &str, max: f32) {
+        let mut maxima = self.maxima.borrow_mut();
+        let properties = maxima.entry(car.to_string()).or_default();
+        // Only a higher maximum is worth writing the file for.
+        if properties
+            .get(property)
+            .is_some_and(|&learned| learned >= max)
+        {
+            return;
+        }
+        properties.insert(property.to_owned(), max);
+        self.changed.set(true);
+    }
+
+    /// Writes the maxima back to the file, if anything was learned since it was last written.
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        if !self.changed.get() {
+            return Ok(());
+        }
+
+        let source = toml::to_string(&*self.maxima.borrow())?;
+        // Write the whole file before replacing the old one, so that it's never left half written.
+        let temporary = self.path.with_extension("tmp");
+        let directory = self.path.parent().unwrap_or_else(|| Path::new(""));
+        fs::create_dir_all(directory)
+            .and_then(|_| fs::write(&temporary, source))
+            .and_then(|_| fs::rename(&temporary, &self.path))
+            .map_err(|e| format!("{}: {}", self.path.display(), e))?;
+
+        self.changed.set(false);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn missing_files_start_without_maxima() {
+        let path = testing::temp_path("missing.toml");
+
+        let learned = LearnedMaxima::load(&path).unwrap();
+        assert_eq!(learned.get(42, "rpm"), None);
+        // Nor is the file written without anything to write.
+        learned.save().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn maxima_round_trip_per_car_and_property() {
+        let path = testing::temp_path("round-trip.toml");
+        let learned = LearnedMaxima::load(&path).unwrap();
+        learned.raise(42, "rpm", 100.0);
+        learned.raise(42, "rpm", 150.0);
+        learned.raise(42, "dash.speed", 80.5);
+        learned.raise(-1, "rpm", 10.0);
+        learned.save().unwrap();
+
+        let loaded = LearnedMaxima::load(&path).unwrap();
+        assert_eq!(loaded.get(42, "rpm"), Some(150.0));
+        assert_eq!(loaded.get(42, "dash.speed"), Some(80.5));
+        assert_eq!(loaded.get(-1, "rpm"), Some(10.0));
+        assert_eq!(loaded.get(7, "rpm"), None);
+        assert!(!path.with_extension("tmp").exists());
+
+        LearnedMaxima::reset(&path).unwrap();
+    }
+
+    #[test]
+    fn saving_creates_the_directory() {
+        let directory = testing::temp_path("learned");
+        let path = directory.join("learned.toml");
+        let learned = LearnedMaxima::load(&path).unwrap();
+        learned.raise(42, "rpm", 100.0);
+        learned.save().unwrap();
+
+        assert_eq!(
+            LearnedMaxima::load(&path).unwrap().get(42, "rpm"),
+            Some(100.0)
+        );
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn resetting_deletes_the_file() {
+        let path = testing::temp_path("reset.toml");
+        let learned = LearnedMaxima::load(&path).unwrap();
+        learned.raise(42, "rpm", 100.0);
+        learned.save().unwrap();
+
+        LearnedMaxima::reset(&path).unwrap();
+        assert!(!path.exists());
+        assert_eq!(LearnedMaxima::load(&path).unwrap().get(42, "rpm"), None);
+        // There's nothing to forget the second time.
+        LearnedMaxima::reset(&path).unwrap();
+    }
+
+    #[test]
+    fn malformed_files_point_at_reset_learned() {
+        let path = testing::temp_path("malformed.toml");
+        fs::write(&path, "[42]\nrpm = \"fast\"\n").unwrap();
+
+        let error = LearnedMaxima::load(&path).err().unwrap().to_string();
+        assert!(error.starts_with(&path.display().to_string()));
+        assert!(error.ends_with("fix or delete the file, or pass --reset-learned"));
+
+        LearnedMaxima::reset(&path).unwrap();
+    }
+
+    #[test]
+    fn only_higher_maxima_are_saved() {
+        let path = testing::temp_path("only-higher-maxima.toml");
+        let learned = LearnedMaxima::load(&path).unwrap();
+        learned.raise(42, "rpm", 100.0);
+        learned.save().unwrap();
+
+        // Nothing new was learned, so the deleted file isn't written again.
+        fs::remove_file(&path).unwrap();
+        learned.raise(42, "rpm", 100.0);
+        learned.raise(42, "rpm", 50.0);
+        learned.save().unwrap();
+        assert!(!path.exists());
+        assert_eq!(learned.get(42, "rpm"), Some(100.0));
+
+        learned.raise(42, "rpm", 150.0);
+        learned.save().unwrap();
+        assert!(path.exists());
+
+        LearnedMaxima::reset(&path).unwrap();
+    }
+}
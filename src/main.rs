@@ -1,20 +1,36 @@
-#![recursion_limit = "256"]
+#![recursion_limit = "512"]
 
 use futures::prelude::*;
-use tokio::prelude::*;
 
-use std::process;
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process,
+    rc::Rc,
+    sync::Arc,
+};
 
-use clap::Arg;
+use chroma::{LightingBackend, NativeBackend, VirtualKeyboard};
+use clap::{Arg, SubCommand};
 use futures_util::pin_mut;
-use tokio::{fs::File, signal::ctrl_c};
+use tokio::{net::lookup_host, signal::ctrl_c};
 
 mod config;
 mod driver;
 mod effects;
+mod expression;
+mod field;
+mod filter;
+mod learned;
+mod preview;
 mod property;
+mod render;
+mod replay;
 mod state;
 mod stream;
+#[cfg(test)]
+mod testing;
+mod watch;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -35,23 +51,202 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             Arg::with_name("format")
                 .short("f")
                 .long("format")
+                .global(true)
+                .help("How the recording is stored")
                 .possible_values(&["json", "raw"])
                 .default_value("raw"),
         )
+        .arg(
+            Arg::with_name("separator")
+                .short("s")
+                .long("separator")
+                .global(true)
+                .help("The separator between datagrams in a JSON recording")
+                .default_value("\n"),
+        )
+        .arg(
+            Arg::with_name("speed")
+                .long("speed")
+                .global(true)
+                .help("How many times faster than recorded to replay")
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("start_at")
+                .long("start-at")
+                .global(true)
+                .help("Where to start replaying, e.g. 90.5, 1:30.5 or lap3")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("end_at")
+                .long("end-at")
+                .global(true)
+                .help("Where to stop replaying, e.g. 90.5, 1:30.5 or lap3")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("loop")
+                .long("loop")
+                .global(true)
+                .help("Starts the replay over after the end"),
+        )
+        .arg(
+            Arg::with_name("interactive")
+                .short("i")
+                .long("interactive")
+                .global(true)
+                .help("Pauses and steps through the replay with commands read from stdin"),
+        )
+        .arg(
+            Arg::with_name("game")
+                .short("g")
+                .long("game")
+                .global(true)
+                .help("The game sending telemetry - detected from the datagrams if omitted")
+                .possible_values(&["horizon4", "horizon5", "motorsport7", "motorsport2023"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("backend")
+                .short("b")
+                .long("backend")
+                .possible_values(&["native", "virtual", "terminal"])
+                .default_value("native"),
+        )
         .arg(
             Arg::with_name("config")
                 .short("c")
                 .long("config")
+                .global(true)
                 .default_value("configs/default.toml"),
         )
+        .arg(
+            Arg::with_name("learned")
+                .long("learned")
+                .global(true)
+                .help(
+                    "Where the maxima learned by auto_raise are kept between sessions - \
+                     forza-chroma/learned.toml in the user's data directory if omitted",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("reset_learned")
+                .long("reset-learned")
+                .help("Forgets the maxima learned by auto_raise"),
+        )
+        .subcommand(
+            SubCommand::with_name("render")
+                .about("Renders the effects for a recording to an animated GIF or PNG sequence")
+                .arg(
+                    Arg::with_name("recording")
+                        .short("r")
+                        .long("recording")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .help("A .gif file, or a directory for a PNG sequence")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Checks the config for problems without touching the keyboard"),
+        )
+        .subcommand(
+            SubCommand::with_name("list-properties")
+                .about("Lists the properties that effects can use as their input"),
+        )
+        .subcommand(
+            SubCommand::with_name("relay")
+                .about("Runs the effects while sending every datagram on to other tools")
+                .arg(
+                    Arg::with_name("local_addr")
+                        .short("l")
+                        .long("local-addr")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("target")
+                        .short("t")
+                        .long("target")
+                        .help("An address to send datagrams on to, e.g. 127.0.0.1:5300")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .required(true),
+                ),
+        )
         .get_matches();
 
-    let mut config = String::new();
-    File::open(matches.value_of("config").unwrap())
-        .await?
-        .read_to_string(&mut config)
+    // Clap can't declare an argument as conflicting with a subcommand.
+    if matches.is_present("recording") && matches.subcommand_matches("relay").is_some() {
+        clap::Error::with_description(
+            "The argument '--recording <recording>' cannot be used with the relay subcommand, \
+             which only relays live telemetry",
+            clap::ErrorKind::ArgumentConflict,
+        )
+        .exit();
+    }
+
+    if matches.subcommand_matches("list-properties").is_some() {
+        list_properties();
+        return Ok(());
+    }
+
+    let learned_path = match matches.value_of("learned") {
+        Some(path) => PathBuf::from(path),
+        None => default_learned_path(),
+    };
+    if matches.is_present("reset_learned") {
+        learned::LearnedMaxima::reset(&learned_path)?;
+    }
+    let learned = match learned::LearnedMaxima::load(&learned_path) {
+        Ok(learned) => Rc::new(learned),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    if let Some(matches) = matches.subcommand_matches("check") {
+        let path = matches.value_of("config").unwrap();
+        load_driver(path, &learned).await;
+        eprintln!("{}: OK", path);
+        return Ok(());
+    }
+
+    let driver = load_driver(matches.value_of("config").unwrap(), &learned).await;
+
+    if let Some(matches) = matches.subcommand_matches("render") {
+        let game = matches.value_of("game").map(str::parse).transpose()?;
+        let stream = stream::telemetry(
+            game,
+            None,
+            matches.value_of("recording"),
+            format(matches),
+            replay::ReplayOptions {
+                start_at: matches.value_of("start_at").map(str::parse).transpose()?,
+                end_at: matches.value_of("end_at").map(str::parse).transpose()?,
+                ..Default::default()
+            },
+            false,
+        )
         .await?;
-    let config: config::Config = toml::from_str(&config)?;
+        pin_mut!(stream);
+
+        let output = Path::new(matches.value_of("output").unwrap());
+        let frames = render::render(&driver, stream, output).await?;
+
+        eprintln!("Rendered {} frames to {}", frames, output.display());
+        return Ok(());
+    }
 
     let local_addr = matches.value_of("local_addr");
     let recording = matches.value_of("recording");
@@ -61,15 +256,58 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         process::exit(1);
     }
 
-    let stream = stream::horizon4(local_addr, recording).await?;
+    let speed: f64 = matches.value_of("speed").unwrap().parse()?;
+    if !(speed > 0.0 && speed.is_finite()) {
+        eprintln!("Error: The replay speed must be positive");
+        process::exit(1);
+    }
+
+    let stream = if let Some(matches) = matches.subcommand_matches("relay") {
+        let mut targets = Vec::new();
+        for target in matches.values_of("target").unwrap() {
+            match lookup_host(target).await?.next() {
+                Some(target) => targets.push(target),
+                None => {
+                    eprintln!("Error: Couldn't resolve relay target {}", target);
+                    process::exit(1);
+                }
+            }
+        }
+
+        let game = matches.value_of("game").map(str::parse).transpose()?;
+        let local_addr = matches
+            .value_of("local_addr")
+            .unwrap_or(stream::DEFAULT_LOCAL_ADDR);
+        Box::pin(forza::relay(local_addr, game, targets).await?)
+    } else {
+        let options = replay::ReplayOptions {
+            speed,
+            start_at: matches.value_of("start_at").map(str::parse).transpose()?,
+            end_at: matches.value_of("end_at").map(str::parse).transpose()?,
+            looping: matches.is_present("loop"),
+            interactive: matches.is_present("interactive"),
+        };
+
+        let game = matches.value_of("game").map(str::parse).transpose()?;
+        stream::telemetry(game, local_addr, recording, format(&matches), options, true).await?
+    };
     pin_mut!(stream);
 
+    let backend: Arc<dyn LightingBackend> = match matches.value_of("backend").unwrap() {
+        "native" => Arc::new(NativeBackend::load()?),
+        // Only the latest frame is kept - this is a dry run of the effects without any hardware.
+        "virtual" => Arc::new(VirtualKeyboard::with_frame_limit(1)),
+        "terminal" => Arc::new(preview::TerminalPreview::new()),
+        _ => unreachable!(),
+    };
+
     let cancellation = ctrl_c().map(|_| ());
     pin_mut!(cancellation);
 
-    let driver = driver::Driver::from_config(&config);
-
-    driver.run(stream, cancellation).await?;
+    let watcher = watch::ConfigWatcher::new(matches.value_of("config").unwrap());
+    driver
+        .run(&backend, stream, cancellation, Some(watcher))
+        .await?;
 
     // while let Some(Ok(datagram)) = stream.next().await {
     //     let mut builder = KeyboardCustomKeyEffectBuilder::new();
@@ -142,3 +380,77 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// Loads the driver for a config, exiting with every problem in it if it's invalid.
+/// Where the learned maxima are kept unless `--learned` says otherwise, which is the user's data
+/// directory: `%APPDATA%` on Windows and `$XDG_DATA_HOME` or `~/.local/share` elsewhere.
+fn default_learned_path() -> PathBuf {
+    let var = |name| env::var_os(name).filter(|value| !value.is_empty());
+    let data = if cfg!(windows) {
+        var("APPDATA").map(PathBuf::from)
+    } else {
+        var("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| var("HOME").map(|home| Path::new(&home).join(".local/share")))
+    };
+
+    // Without any of those, it's kept in the current directory.
+    data.unwrap_or_default()
+        .join("forza-chroma")
+        .join("learned.toml")
+}
+
+async fn load_driver(path: &str, learned: &Rc<learned::LearnedMaxima>) -> driver::Driver {
+    match driver::Driver::load(path, learned).await {
+        Ok(driver) => driver,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn list_properties() {
+    let properties = property::list();
+    let width = properties.iter().map(|p| p.name.len()).max().unwrap_or(0);
+
+    println!(
+        "{:<width$}  {:<6}  {:<5}  MAX",
+        "NAME",
+        "KIND",
+        "UNIT",
+        width = width
+    );
+    for property in properties {
+        let max = match property.max {
+            property::DefaultMax::Telemetry => "from the telemetry".to_owned(),
+            property::DefaultMax::Value(max) => max.to_string(),
+            property::DefaultMax::None => "needs max_value or auto_raise".to_owned(),
+            property::DefaultMax::NotApplicable => "-".to_owned(),
+        };
+        let unit = if property.unit.is_empty() {
+            "-"
+        } else {
+            property.unit
+        };
+
+        println!(
+            "{:<width$}  {:<6}  {:<5}  {}",
+            property.name,
+            property.kind,
+            unit,
+            max,
+            width = width
+        );
+    }
+}
+
+fn format<'a>(matches: &'a clap::ArgMatches) -> stream::Format<'a> {
+    match matches.value_of("format").unwrap() {
+        "raw" => stream::Format::Raw,
+        "json" => stream::Format::Json {
+            separator: matches.value_of("separator").unwrap(),
+        },
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,90 @@
+use std::io::{self, Write};
+
+use chroma::{sys::RZEFFECTID, Frame, Key, KeyboardEffect, LightingBackend, VirtualKeyboard};
+use rgb::RGB8;
+
+/// Width of a single key on the terminal, in characters.
+const KEY_WIDTH: usize = 3;
+
+/// Renders the keyboard in the terminal using truecolor ANSI escape codes. The whole grid is
+/// redrawn every time an effect is applied.
+pub struct TerminalPreview {
+    keyboard: VirtualKeyboard,
+}
+
+impl TerminalPreview {
+    pub fn new() -> Self {
+        // Clear the screen once so that each redraw can just move the cursor back home.
+        print!("\x1b[2J");
+
+        Self {
+            keyboard: VirtualKeyboard::with_frame_limit(1),
+        }
+    }
+
+    fn render(&self, frame: &Frame) -> io::Result<()> {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+
+        write!(out, "\x1b[H")?;
+        for row in 0..chroma::MAX_ROW {
+            for column in 0..chroma::MAX_COLUMN {
+                let color = frame.position(row, column);
+                let label = Key::at(row, column).map_or("", |key| key.label());
+                let text = foreground(color);
+
+                write!(
+                    out,
+                    "\x1b[48;2;{};{};{}m\x1b[38;2;{};{};{}m{:^width$}",
+                    color.r,
+                    color.g,
+                    color.b,
+                    text.r,
+                    text.g,
+                    text.b,
+                    label,
+                    width = KEY_WIDTH
+                )?;
+            }
+            writeln!(out, "\x1b[0m")?;
+        }
+
+        out.flush()
+    }
+}
+
+impl LightingBackend for TerminalPreview {
+    fn create_keyboard_effect(&self, effect: &KeyboardEffect) -> chroma::Result<RZEFFECTID> {
+        self.keyboard.create_keyboard_effect(effect)
+    }
+
+    fn set_effect(&self, id: RZEFFECTID) -> chroma::Result<()> {
+        self.keyboard.set_effect(id)?;
+
+        if let Some(frame) = self.keyboard.current() {
+            // A broken terminal shouldn't take down the session - the next frame will try again.
+            let _ = self.render(&frame);
+        }
+
+        Ok(())
+    }
+
+    fn delete_effect(&self, id: RZEFFECTID) -> chroma::Result<()> {
+        self.keyboard.delete_effect(id)
+    }
+}
+
+/// Picks a label color that stays readable on top of the key color.
+fn foreground(background: RGB8) -> RGB8 {
+    let luma =
+        0.299 * background.r as f32 + 0.587 * background.g as f32 + 0.114 * background.b as f32;
+    if luma > 128.0 {
+        RGB8 { r: 0, g: 0, b: 0 }
+    } else {
+        RGB8 {
+            r: 0x80,
+            g: 0x80,
+            b: 0x80,
+        }
+    }
+}
@@ -1,17 +1,58 @@
-use std::{cell::Cell, collections::HashMap, iter::FromIterator};
+use std::{cell::Cell, collections::HashMap, iter::FromIterator, rc::Rc};
 
-use forza::Horizon4Datagram;
+use forza::{Quad, Telemetry};
 
-use crate::config;
+use crate::{
+    config::{self, Problem},
+    expression::{Expression, ParseError},
+    field::{self, Field},
+    filter::Filter,
+    learned::LearnedMaxima,
+};
 
 pub enum Property {
     Rate(RateProperty),
     Score(ScoreProperty),
+    Quad(QuadProperty),
+    Vector(VectorProperty),
 }
 
-pub fn query_property(config: &config::Input) -> Property {
-    let query = &PROPERTIES[&config.property[..]];
+/// Looks up one of the named properties or telemetry fields, or failing that parses the property as
+/// an expression. Fields and expressions are rate properties, and the path of a quad without its
+/// part, e.g. `dash.tire_temp`, is a quad property. Fields default to their natural
+/// maximum, if they have one, and expressions are taken to be a fraction of 1 unless the config
+/// gives them a `max_value` or has them `auto_raise`.
+///
+/// The config's filters are applied to rate properties.
+pub fn query_property(
+    config: &config::Input,
+    learned: &Rc<LearnedMaxima>,
+) -> Result<Property, Vec<Problem>> {
+    match (find_property(config, learned), Filter::from_config(config)) {
+        (Ok(Property::Rate(property)), Ok(filter)) => {
+            Ok(Property::Rate(RateProperty { filter, ..property }))
+        }
+        (Ok(Property::Score(_) | Property::Quad(_) | Property::Vector(_)), Ok(filter))
+            if !filter.is_identity() =>
+        {
+            Err(vec![Problem::new(
+                "input.property",
+                format!(
+                    "property '{}' isn't a rate, so it can't be filtered",
+                    config.property
+                ),
+            )])
+        }
+        (Ok(property), Ok(_)) => Ok(property),
+        (property, filter) => Err(property
+            .err()
+            .into_iter()
+            .chain(filter.err().into_iter().flatten())
+            .collect()),
+    }
+}
 
+fn find_property(config: &config::Input, learned: &Rc<LearnedMaxima>) -> Result<Property, Problem> {
     let max_value = if let Some(max) = config.max_value {
         Some(Cell::new(max))
     } else if config.auto_raise {
@@ -19,15 +60,151 @@ pub fn query_property(config: &config::Input) -> Property {
     } else {
         None
     };
+    let auto_raise = || {
+        config.auto_raise.then(|| AutoRaise {
+            learned: learned.clone(),
+            property: config.property.clone(),
+            initial: config.max_value.unwrap_or(0.0),
+            car: Cell::new(None),
+        })
+    };
+
+    if let Some(query) = PROPERTIES.get(&config.property[..]) {
+        return Ok(match query {
+            PropertyQuery::Rate(query) => Property::Rate(RateProperty {
+                query: RateQuery::Named(query),
+                max_value,
+                auto_raise: auto_raise(),
+                filter: Filter::default(),
+            }),
+            PropertyQuery::Score(query) => Property::Score(ScoreProperty { query }),
+            PropertyQuery::Vector(query) => {
+                if config.auto_raise {
+                    return Err(Problem::new(
+                        "input.auto_raise",
+                        format!(
+                            "property '{}' is a vector, which can't auto_raise - set max_value \
+                             instead",
+                            config.property
+                        ),
+                    ));
+                }
+                let max = config.max_value.unwrap_or(query.max);
+                if !(max > 0.0 && max.is_finite()) {
+                    return Err(Problem::new(
+                        "input.max_value",
+                        "the maximum has to be positive",
+                    ));
+                }
+                Property::Vector(VectorProperty { query, max })
+            }
+        });
+    }
 
-    match query {
-        PropertyQuery::Rate(query) => Property::Rate(RateProperty {
-            query,
-            max_value,
-            auto_raise: config.auto_raise,
-        }),
-        PropertyQuery::Score(query) => Property::Score(ScoreProperty { query }),
+    if let Some(fields) = field::quad(&config.property) {
+        return Ok(Property::Quad(QuadProperty { fields }));
     }
+
+    let (expression, default_max) = match field::get(&config.property) {
+        Some(field) => (Expression::Field(field), field.max),
+        None => (
+            Expression::parse(&config.property)
+                .map_err(|e| unknown_property(&config.property, e))?,
+            Some(1.0),
+        ),
+    };
+
+    Ok(Property::Rate(RateProperty {
+        query: RateQuery::Expression(expression),
+        max_value: max_value.or_else(|| default_max.map(Cell::new)),
+        auto_raise: auto_raise(),
+        filter: Filter::default(),
+    }))
+}
+
+fn unknown_property(property: &str, error: ParseError) -> Problem {
+    // A lone word was most likely meant to be one of the named properties.
+    let message = if property
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        let mut names: Vec<_> = PROPERTIES.keys().copied().collect();
+        names.sort_unstable();
+        format!(
+            "unknown property '{}' - expected one of {}, a field from list-properties or an \
+             expression",
+            property,
+            names.join(", ")
+        )
+    } else {
+        format!("invalid expression '{}': {}", property, error)
+    };
+
+    Problem::new("input.property", message)
+}
+
+/// A property that can be used by name, for listing.
+pub struct PropertyInfo {
+    pub name: &'static str,
+    pub kind: &'static str,
+    /// Empty for properties without a unit.
+    pub unit: &'static str,
+    pub max: DefaultMax,
+}
+
+/// What a rate property is a fraction of when the config doesn't give a `max_value`.
+pub enum DefaultMax {
+    /// The maximum comes from the telemetry, e.g. the engine's redline.
+    Telemetry,
+    Value(f32),
+    /// The config has to give a `max_value` or set `auto_raise`.
+    None,
+    /// Score and quad properties have no maximum.
+    NotApplicable,
+}
+
+/// Every named property followed by every telemetry field and quad.
+pub fn list() -> Vec<PropertyInfo> {
+    let mut names: Vec<_> = PROPERTIES.iter().collect();
+    names.sort_unstable_by_key(|&(name, _)| name);
+
+    let named = names.into_iter().map(|(&name, query)| match query {
+        PropertyQuery::Rate(query) => PropertyInfo {
+            name,
+            kind: "rate",
+            unit: query.unit,
+            max: match query.max {
+                Some(_) => DefaultMax::Telemetry,
+                None => DefaultMax::None,
+            },
+        },
+        PropertyQuery::Score(query) => PropertyInfo {
+            name,
+            kind: "score",
+            unit: query.unit,
+            max: DefaultMax::NotApplicable,
+        },
+        PropertyQuery::Vector(query) => PropertyInfo {
+            name,
+            kind: "vector",
+            unit: query.unit,
+            max: DefaultMax::Value(query.max),
+        },
+    });
+    let fields = field::all().iter().map(|field| PropertyInfo {
+        name: field.path,
+        kind: "rate",
+        unit: field.unit,
+        max: field.max.map_or(DefaultMax::None, DefaultMax::Value),
+    });
+    let quads = field::quads().map(|path| PropertyInfo {
+        name: path,
+        kind: "quad",
+        unit: field::quad(path).unwrap().front_left.unit,
+        max: DefaultMax::NotApplicable,
+    });
+
+    named.chain(fields).chain(quads).collect()
 }
 
 lazy_static::lazy_static! {
@@ -40,41 +217,79 @@ fn properties() -> HashMap<&'static str, PropertyQuery> {
         (
             "speed",
             PropertyQuery::Rate(RatePropertyQuery {
-                current: |datagram| datagram.dash.speed,
+                current: |telemetry| telemetry.dash.map(|dash| dash.speed),
                 max: None,
+                unit: "m/s",
             }),
         ),
         (
             "rpm",
             PropertyQuery::Rate(RatePropertyQuery {
-                current: |datagram| datagram.sled.current_engine_rpm,
-                max: Some(|datagram| datagram.sled.engine_max_rpm),
+                current: |telemetry| Some(telemetry.sled.current_engine_rpm),
+                max: Some(|telemetry| telemetry.sled.engine_max_rpm),
+                unit: "rpm",
             }),
         ),
         (
             "rpm-baseline",
             PropertyQuery::Rate(RatePropertyQuery {
-                current: |datagram| {
-                    datagram.sled.current_engine_rpm - datagram.sled.engine_idle_rpm
+                current: |telemetry| {
+                    Some(telemetry.sled.current_engine_rpm - telemetry.sled.engine_idle_rpm)
                 },
-                max: Some(|datagram| datagram.sled.engine_max_rpm - datagram.sled.engine_idle_rpm),
+                max: Some(|telemetry| {
+                    telemetry.sled.engine_max_rpm - telemetry.sled.engine_idle_rpm
+                }),
+                unit: "rpm",
             }),
         ),
         (
             "driveline",
             PropertyQuery::Rate(RatePropertyQuery {
-                current: |datagram| {
-                    let line = datagram.dash.normalized_driving_line as i16;
+                current: |telemetry| {
+                    let line = telemetry.dash?.normalized_driving_line as i16;
                     let line = line + 127;
-                    line as f32
+                    Some(line as f32)
                 },
                 max: Some(|_| 255.0),
+                unit: "",
+            }),
+        ),
+        (
+            // The most worn tire. Only Forza Motorsport (2023) reports tire wear.
+            "tire-wear",
+            PropertyQuery::Rate(RatePropertyQuery {
+                current: |telemetry| {
+                    let wear = telemetry.tire_wear?;
+                    Some(
+                        wear.front_left
+                            .max(wear.front_right)
+                            .max(wear.rear_left)
+                            .max(wear.rear_right),
+                    )
+                },
+                max: Some(|_| 1.0),
+                unit: "",
+            }),
+        ),
+        (
+            // The force that pushes the driver around, which is the opposite of the car's
+            // acceleration: to the right when turning left and forwards when braking.
+            "g-force",
+            PropertyQuery::Vector(VectorPropertyQuery {
+                current: |telemetry| {
+                    let acceleration = telemetry.sled.acceleration;
+                    Some((-acceleration.x, -acceleration.z))
+                },
+                // 2 g.
+                max: 19.6133,
+                unit: "m/s²",
             }),
         ),
         (
             "position",
             PropertyQuery::Score(ScorePropertyQuery {
-                current: |datagram| (datagram.dash.race_position as i32) - 1,
+                current: |telemetry| Some((telemetry.dash?.race_position as i32) - 1),
+                unit: "",
             }),
         ),
     ])
@@ -83,29 +298,75 @@ fn properties() -> HashMap<&'static str, PropertyQuery> {
 enum PropertyQuery {
     Rate(RatePropertyQuery),
     Score(ScorePropertyQuery),
+    Vector(VectorPropertyQuery),
 }
 
 struct RatePropertyQuery {
-    current: fn(&Horizon4Datagram) -> f32,
-    max: Option<fn(&Horizon4Datagram) -> f32>,
+    current: fn(&Telemetry) -> Option<f32>,
+    max: Option<fn(&Telemetry) -> f32>,
+    unit: &'static str,
+}
+
+/// Where a rate property's value comes from.
+enum RateQuery {
+    Named(&'static RatePropertyQuery),
+    Expression(Expression),
 }
 
 pub struct RateProperty {
-    query: &'static RatePropertyQuery,
+    query: RateQuery,
     max_value: Option<Cell<f32>>,
-    auto_raise: bool,
+    auto_raise: Option<AutoRaise>,
+    filter: Filter,
+}
+
+/// Raises the maximum whenever the property goes above it, remembering the maximum for each car.
+struct AutoRaise {
+    learned: Rc<LearnedMaxima>,
+    /// The property as written in the config.
+    property: String,
+    /// The maximum to start from for cars that haven't been driven before.
+    initial: f32,
+    /// The car that the maximum is currently for.
+    car: Cell<Option<i32>>,
 }
 
 impl RateProperty {
-    pub fn query(&self, datagram: &Horizon4Datagram) -> f32 {
-        let current = (self.query.current)(datagram);
-        let max = match self.query.max {
-            Some(query) => query(datagram),
+    /// Whether the property has a maximum to be a rate of, either its own or one from the config.
+    pub fn has_max(&self) -> bool {
+        let named_max = match &self.query {
+            RateQuery::Named(query) => query.max.is_some(),
+            RateQuery::Expression(_) => false,
+        };
+        named_max || self.max_value.is_some()
+    }
+
+    /// Shapes the property's value before it's shown.
+    pub fn filter(&self) -> &Filter {
+        &self.filter
+    }
+
+    /// Returns `None` if the telemetry doesn't carry this property. The value hasn't been through
+    /// the filter yet.
+    pub fn query(&self, telemetry: &Telemetry) -> Option<f32> {
+        let (current, max) = match &self.query {
+            RateQuery::Named(query) => ((query.current)(telemetry)?, query.max),
+            RateQuery::Expression(expression) => (expression.evaluate(telemetry)?, None),
+        };
+        let max = match max {
+            Some(query) => query(telemetry),
             None => {
                 let max_value = self.max_value.as_ref().unwrap();
-                if self.auto_raise {
+                if let Some(auto_raise) = &self.auto_raise {
+                    let car = telemetry.sled.car_ordinal;
+                    if auto_raise.car.replace(Some(car)) != Some(car) {
+                        let learned = auto_raise.learned.get(car, &auto_raise.property);
+                        max_value.set(learned.unwrap_or(0.0).max(auto_raise.initial));
+                    }
+
                     if current > max_value.get() {
                         max_value.set(current);
+                        auto_raise.learned.raise(car, &auto_raise.property, current);
                     }
                 }
 
@@ -114,12 +375,13 @@ impl RateProperty {
         };
 
         // bound the ratio between 0.0 and 1.0
-        (current / max).min(1.0).max(0.0)
+        Some((current / max).clamp(0.0, 1.0))
     }
 }
 
 struct ScorePropertyQuery {
-    current: fn(&Horizon4Datagram) -> i32,
+    current: fn(&Telemetry) -> Option<i32>,
+    unit: &'static str,
 }
 
 pub struct ScoreProperty {
@@ -127,7 +389,142 @@ pub struct ScoreProperty {
 }
 
 impl ScoreProperty {
-    pub fn query(&self, datagram: &Horizon4Datagram) -> i32 {
-        (self.query.current)(datagram)
+    /// Returns `None` if the telemetry doesn't carry this property.
+    pub fn query(&self, telemetry: &Telemetry) -> Option<i32> {
+        (self.query.current)(telemetry)
+    }
+}
+
+/// A value for each wheel, read from the four fields of a quad.
+pub struct QuadProperty {
+    fields: Quad<&'static Field>,
+}
+
+impl QuadProperty {
+    /// Returns `None` if the telemetry doesn't carry this property.
+    pub fn query(&self, telemetry: &Telemetry) -> Option<Quad<f32>> {
+        Some(Quad {
+            front_left: self.fields.front_left.read(telemetry)?,
+            front_right: self.fields.front_right.read(telemetry)?,
+            rear_left: self.fields.rear_left.read(telemetry)?,
+            rear_right: self.fields.rear_right.read(telemetry)?,
+        })
+    }
+}
+
+struct VectorPropertyQuery {
+    /// To the right and forwards, from the driver's point of view.
+    current: fn(&Telemetry) -> Option<(f32, f32)>,
+    /// The length that a config without a `max_value` takes to be full scale.
+    max: f32,
+    unit: &'static str,
+}
+
+/// A direction and strength in the plane of the car, e.g. the g-force.
+pub struct VectorProperty {
+    query: &'static VectorPropertyQuery,
+    max: f32,
+}
+
+impl VectorProperty {
+    /// Returns `None` if the telemetry doesn't carry this property. Each component is a fraction of
+    /// the maximum, between -1.0 and 1.0.
+    pub fn query(&self, telemetry: &Telemetry) -> Option<(f32, f32)> {
+        let (right, forwards) = (self.query.current)(telemetry)?;
+        let scale = |value: f32| (value / self.max).clamp(-1.0, 1.0);
+        Some((scale(right), scale(forwards)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn query(config: &str) -> Result<Property, Vec<Problem>> {
+        let config: config::Input = toml::from_str(config).unwrap();
+        query_property(&config, &testing::learned())
+    }
+
+    fn problem(config: &str) -> (&'static str, String) {
+        match query(config) {
+            Ok(_) => panic!("expected a problem with {}", config),
+            Err(problems) => (problems[0].key, problems[0].message.clone()),
+        }
+    }
+
+    #[test]
+    fn vectors_have_a_positive_maximum() {
+        assert!(matches!(
+            query("property = \"g-force\""),
+            Ok(Property::Vector(VectorProperty { max, .. })) if max == 19.6133
+        ));
+        assert!(matches!(
+            query("property = \"g-force\"\nmax_value = 9.8"),
+            Ok(Property::Vector(VectorProperty { max, .. })) if max == 9.8
+        ));
+
+        for max in ["0.0", "-9.8", "nan", "inf"] {
+            assert_eq!(
+                problem(&format!("property = \"g-force\"\nmax_value = {}", max)),
+                (
+                    "input.max_value",
+                    "the maximum has to be positive".to_owned()
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn vectors_cant_auto_raise() {
+        assert_eq!(
+            problem("property = \"g-force\"\nauto_raise = true").0,
+            "input.auto_raise"
+        );
+        assert_eq!(
+            problem("property = \"g-force\"\nauto_raise = true\nmax_value = 9.8").0,
+            "input.auto_raise"
+        );
+    }
+
+    #[test]
+    fn vectors_cant_be_filtered() {
+        assert_eq!(
+            problem("property = \"g-force\"\nsmoothing = 0.1").0,
+            "input.property"
+        );
+    }
+
+    #[test]
+    fn auto_raise_switches_maxima_with_the_car() {
+        let path = testing::temp_path("switches-maxima.toml");
+        let learned = LearnedMaxima::load(&path).unwrap();
+        learned.raise(1, "dash.speed", 200.0);
+        let learned = Rc::new(learned);
+
+        let config: config::Input =
+            toml::from_str("property = \"dash.speed\"\nmax_value = 50\nauto_raise = true").unwrap();
+        let property = match query_property(&config, &learned) {
+            Ok(Property::Rate(property)) => property,
+            _ => panic!("expected a rate property"),
+        };
+        let driving = |car, speed| {
+            let mut telemetry = testing::telemetry(1000.0);
+            telemetry.sled.car_ordinal = car;
+            telemetry.dash.as_mut().unwrap().speed = speed;
+            property.query(&telemetry).unwrap()
+        };
+
+        // What was learned for the car.
+        assert_eq!(driving(1, 100.0), 0.5);
+        // A car that hasn't been driven starts from max_value, and raises it.
+        assert_eq!(driving(2, 25.0), 0.5);
+        assert_eq!(driving(2, 100.0), 1.0);
+        assert_eq!(driving(2, 50.0), 0.5);
+        assert_eq!(learned.get(2, "dash.speed"), Some(100.0));
+        // Going back to the first car goes back to its maximum.
+        assert_eq!(driving(1, 100.0), 0.5);
+        assert_eq!(driving(1, 250.0), 1.0);
+        assert_eq!(learned.get(1, "dash.speed"), Some(250.0));
     }
 }
@@ -0,0 +1,282 @@
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chroma::{Frame, Key, LightingBackend, VirtualKeyboard};
+use rgb::RGB8;
+use tokio::stream::{Stream, StreamExt};
+
+use crate::driver::Driver;
+
+/// Size of a key in pixels.
+const KEY_SIZE: usize = 16;
+/// Space between keys in pixels.
+const KEY_GAP: usize = 4;
+
+const WIDTH: usize = chroma::MAX_COLUMN as usize * (KEY_SIZE + KEY_GAP) + KEY_GAP;
+const HEIGHT: usize = chroma::MAX_ROW as usize * (KEY_SIZE + KEY_GAP) + KEY_GAP;
+
+const BACKGROUND: RGB8 = RGB8 {
+    r: 0x10,
+    g: 0x10,
+    b: 0x10,
+};
+
+/// Keys that aren't lit are still drawn so that the keyboard keeps its shape.
+const UNLIT_KEY: RGB8 = RGB8 {
+    r: 0x30,
+    g: 0x30,
+    b: 0x30,
+};
+
+/// Runs the driver over the whole stream as fast as possible, writing every frame to `output`.
+/// Frames are timed by the recorded timestamps rather than the wall clock. If `output` ends in
+/// `.gif` an animated GIF is written, otherwise `output` is a directory that receives a numbered
+/// PNG sequence.
+///
+/// Returns the number of frames rendered.
+pub async fn render(
+    driver: &Driver,
+    mut stream: impl Stream<Item = forza::Result<forza::Telemetry>> + Unpin,
+    output: &Path,
+) -> Result<usize, Box<dyn Error>> {
+    let keyboard = Arc::new(VirtualKeyboard::new());
+    let backend: Arc<dyn LightingBackend> = keyboard.clone();
+
+    let mut sink: Box<dyn FrameSink> = match output.extension() {
+        Some(extension) if extension == "gif" => Box::new(GifSink::create(output)?),
+        _ => Box::new(PngSequence::create(output)?),
+    };
+
+    let mut session = driver.start();
+    let start = Instant::now();
+    let mut first_recorded_time = None;
+    let mut count = 0;
+
+    while let Some(telemetry) = stream.next().await {
+        let telemetry = telemetry?;
+
+        let first_recorded_time = *first_recorded_time.get_or_insert(telemetry.sled.timestamp_ms);
+        let since_first_time = Duration::from_millis(
+            telemetry
+                .sled
+                .timestamp_ms
+                .wrapping_sub(first_recorded_time) as u64,
+        );
+
+        session
+            .step(&telemetry, start + since_first_time)
+            .apply(&backend)?;
+
+        for frame in keyboard.take_frames() {
+            sink.write(&rasterize(&frame), since_first_time)?;
+            count += 1;
+        }
+    }
+
+    sink.finish()?;
+
+    Ok(count)
+}
+
+/// Draws a frame as tightly packed 8-bit RGB pixels.
+fn rasterize(frame: &Frame) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(WIDTH * HEIGHT * 3);
+    for _ in 0..(WIDTH * HEIGHT) {
+        pixels.extend_from_slice(&[BACKGROUND.r, BACKGROUND.g, BACKGROUND.b]);
+    }
+
+    for row in 0..chroma::MAX_ROW {
+        for column in 0..chroma::MAX_COLUMN {
+            if Key::at(row, column).is_none() {
+                continue;
+            }
+
+            let color = match frame.position(row, column) {
+                RGB8 { r: 0, g: 0, b: 0 } => UNLIT_KEY,
+                color => color,
+            };
+
+            let top = KEY_GAP + row as usize * (KEY_SIZE + KEY_GAP);
+            let left = KEY_GAP + column as usize * (KEY_SIZE + KEY_GAP);
+            for y in top..(top + KEY_SIZE) {
+                for x in left..(left + KEY_SIZE) {
+                    let i = (y * WIDTH + x) * 3;
+                    pixels[i..i + 3].copy_from_slice(&[color.r, color.g, color.b]);
+                }
+            }
+        }
+    }
+
+    pixels
+}
+
+trait FrameSink {
+    /// Writes a frame that is shown starting `at` into the session.
+    fn write(&mut self, pixels: &[u8], at: Duration) -> Result<(), Box<dyn Error>>;
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>>;
+}
+
+struct PngSequence {
+    directory: PathBuf,
+    next: usize,
+}
+
+impl PngSequence {
+    fn create(directory: &Path) -> std::io::Result<Self> {
+        fs::create_dir_all(directory)?;
+
+        Ok(Self {
+            directory: directory.to_owned(),
+            next: 0,
+        })
+    }
+}
+
+impl FrameSink for PngSequence {
+    fn write(&mut self, pixels: &[u8], _at: Duration) -> Result<(), Box<dyn Error>> {
+        let path = self.directory.join(format!("frame-{:06}.png", self.next));
+        self.next += 1;
+
+        let mut encoder = png::Encoder::new(
+            BufWriter::new(File::create(path)?),
+            WIDTH as u32,
+            HEIGHT as u32,
+        );
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(pixels)?;
+
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+struct GifSink {
+    encoder: gif::Encoder<BufWriter<File>>,
+    // GIF frame delays are in hundredths of a second, so a frame can only be written out once the
+    // next one says how long it was shown for.
+    pending: Option<(Vec<u8>, u64)>,
+}
+
+impl GifSink {
+    /// How long the last frame is shown for, in hundredths of a second.
+    const LAST_FRAME_DELAY: u16 = 2;
+
+    fn create(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut encoder = gif::Encoder::new(
+            BufWriter::new(File::create(path)?),
+            WIDTH as u16,
+            HEIGHT as u16,
+            &[],
+        )?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+
+        Ok(Self {
+            encoder,
+            pending: None,
+        })
+    }
+
+    fn write_frame(&mut self, pixels: &[u8], delay: u16) -> Result<(), Box<dyn Error>> {
+        let mut frame = gif::Frame::from_rgb_speed(WIDTH as u16, HEIGHT as u16, pixels, 10);
+        frame.delay = delay;
+        self.encoder.write_frame(&frame)?;
+        Ok(())
+    }
+}
+
+impl FrameSink for GifSink {
+    fn write(&mut self, pixels: &[u8], at: Duration) -> Result<(), Box<dyn Error>> {
+        let at = at.as_millis() as u64 / 10;
+
+        if let Some((previous, previous_at)) = self.pending.take() {
+            // Frames that fall within the same hundredth of a second would never be shown anyway.
+            if at > previous_at {
+                let delay = (at - previous_at).min(u16::MAX as u64) as u16;
+                self.write_frame(&previous, delay)?;
+            }
+        }
+
+        self.pending = Some((pixels.to_vec(), at));
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        if let Some((pixels, _)) = self.pending.take() {
+            self.write_frame(&pixels, Self::LAST_FRAME_DELAY)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+
+    use crate::testing;
+
+    /// The delay of every frame in the GIF at `path`, in hundredths of a second.
+    fn delays(path: &std::path::Path) -> Vec<u16> {
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(File::open(path).unwrap())
+            .unwrap();
+        let mut delays = vec![];
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        delays
+    }
+
+    #[tokio::test]
+    async fn gif_frames_are_timed_by_the_recorded_timestamps() {
+        let driver = testing::driver(
+            r#"
+            [[effect]]
+            [effect.input]
+            property = "rpm-baseline"
+            [effect.output]
+            type = "meter"
+            color = "white"
+            [effect.output.keyboard]
+            column = "0->9"
+            row = 1
+            "#,
+        );
+
+        // The timestamps wrap around after the first datagram, and the third datagram lands in the
+        // same hundredth of a second as the second, which it replaces.
+        let recording = [
+            (u32::MAX - 39, 2000.0),
+            (0, 3000.0),
+            (5, 4000.0),
+            (960, 5000.0),
+        ]
+        .iter()
+        .map(|&(timestamp_ms, rpm)| {
+            let mut telemetry = testing::telemetry(rpm);
+            telemetry.sled.timestamp_ms = timestamp_ms;
+            Ok(telemetry)
+        })
+        .collect::<Vec<_>>();
+
+        let path = testing::temp_path("render.gif");
+        let frames = super::render(&driver, tokio::stream::iter(recording), &path)
+            .await
+            .unwrap();
+        assert_eq!(frames, 4);
+        assert_eq!(delays(&path), [4, 96, super::GifSink::LAST_FRAME_DELAY]);
+
+        fs::remove_file(path).unwrap();
+    }
+}
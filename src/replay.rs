@@ -0,0 +1,290 @@
+use std::{future::Future, io::BufRead, str::FromStr, thread};
+
+use tokio::{
+    io,
+    stream::StreamExt,
+    sync::mpsc,
+    time::{delay_until, Duration, Instant},
+};
+
+use forza::Telemetry;
+
+use crate::stream::{RecordedStream, TelemetryStream};
+
+/// How a recording is replayed.
+#[derive(Clone, Debug)]
+pub struct ReplayOptions {
+    /// How many times faster than recorded to replay.
+    pub speed: f64,
+    /// Where to start replaying, skipping everything before.
+    pub start_at: Option<Position>,
+    /// Where to stop replaying.
+    pub end_at: Option<Position>,
+    /// Whether to start over after the end.
+    pub looping: bool,
+    /// Whether to take pause and step commands from stdin.
+    pub interactive: bool,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            start_at: None,
+            end_at: None,
+            looping: false,
+            interactive: false,
+        }
+    }
+}
+
+/// A point in a recording.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Position {
+    /// The time since the first datagram in the recording.
+    Time(Duration),
+    /// The start of a lap, counting from 1.
+    Lap(u16),
+}
+
+impl Position {
+    fn reached(self, at: Duration, telemetry: &Telemetry) -> bool {
+        match self {
+            Position::Time(time) => at >= time,
+            // Formats without a dash don't know about laps.
+            Position::Lap(lap) => telemetry
+                .dash
+                .is_some_and(|dash| dash.lap_number as u32 + 1 >= lap as u32),
+        }
+    }
+}
+
+impl FromStr for Position {
+    type Err = String;
+
+    /// Parses a lap such as `lap3`, or a time in seconds such as `90.5` or `1:30.5`. Seconds after
+    /// minutes must be less than 60.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(lap) = s.strip_prefix("lap") {
+            return match lap.parse() {
+                Ok(lap) if lap > 0 => Ok(Position::Lap(lap)),
+                _ => Err(format!("invalid lap '{}' - laps count from lap1", s)),
+            };
+        }
+
+        let invalid = || format!("invalid position '{}' - expected a time or a lap", s);
+        let (minutes, seconds) = match s.find(':') {
+            Some(i) => (
+                Some(s[..i].parse::<u64>().map_err(|_| invalid())?),
+                &s[i + 1..],
+            ),
+            None => (None, s),
+        };
+        let seconds: f64 = seconds.parse().map_err(|_| invalid())?;
+        if seconds.is_sign_negative() || minutes.is_some() && seconds >= 60.0 {
+            return Err(invalid());
+        }
+
+        let minutes = minutes.unwrap_or(0).checked_mul(60).ok_or_else(invalid)?;
+        Duration::try_from_secs_f64(seconds)
+            .ok()
+            .and_then(|seconds| Duration::from_secs(minutes).checked_add(seconds))
+            .map(Position::Time)
+            .ok_or_else(invalid)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Command {
+    TogglePause,
+    Step,
+    Quit,
+}
+
+/// Replays `first`, paced by the recorded times unless `realtime` is off. `reopen` opens the
+/// recording again when looping.
+pub fn play<'a, F, Fut>(
+    first: RecordedStream<'a>,
+    reopen: F,
+    options: ReplayOptions,
+    realtime: bool,
+) -> TelemetryStream<'a>
+where
+    F: 'a + Fn() -> Fut,
+    Fut: 'a + Future<Output = io::Result<RecordedStream<'a>>>,
+{
+    let mut controls = if realtime && options.interactive {
+        Some(Controls::new())
+    } else {
+        None
+    };
+
+    Box::pin(async_stream::stream! {
+        let mut recording = first;
+        'replay: loop {
+            let mut started = false;
+            let mut replayed_any = false;
+            // The wall-clock time at which a recorded time was replayed, which the rest of the
+            // replay is timed from.
+            let mut clock: Option<(Instant, Duration)> = None;
+
+            while let Some(item) = recording.next().await {
+                let (at, telemetry) = match item {
+                    Ok(item) => item,
+                    Err(e @ forza::Error::Decode(_)) => {
+                        yield Err(e);
+                        continue;
+                    }
+                    Err(e) => {
+                        yield Err(e);
+                        break 'replay;
+                    }
+                };
+
+                if !started {
+                    started = options.start_at.is_none_or(|start| start.reached(at, &telemetry));
+                    if !started {
+                        continue;
+                    }
+                }
+                if options.end_at.is_some_and(|end| end.reached(at, &telemetry)) {
+                    break;
+                }
+
+                if realtime {
+                    if let Some(controls) = controls.as_mut() {
+                        match controls.next().await {
+                            Flow::Play => {}
+                            // Time the rest of the replay from here.
+                            Flow::Resume => clock = None,
+                            Flow::Quit => break 'replay,
+                        }
+                    }
+
+                    let (start_time, first_at) = *clock.get_or_insert((Instant::now(), at));
+                    let since_first = at.checked_sub(first_at).unwrap_or_default();
+                    delay_until(start_time + since_first.div_f64(options.speed)).await;
+                }
+
+                replayed_any = true;
+                yield Ok(telemetry);
+            }
+
+            // Don't spin on a recording that has nothing to replay.
+            if !options.looping || !replayed_any {
+                break;
+            }
+
+            recording = match reopen().await {
+                Ok(recording) => recording,
+                Err(e) => {
+                    yield Err(e.into());
+                    break;
+                }
+            };
+        }
+    })
+}
+
+enum Flow {
+    Play,
+    /// The replay was paused before this datagram.
+    Resume,
+    Quit,
+}
+
+/// Pauses and steps through a replay with commands read from stdin, one per line.
+struct Controls {
+    commands: mpsc::UnboundedReceiver<Command>,
+    paused: bool,
+}
+
+impl Controls {
+    fn new() -> Self {
+        eprintln!("Replay controls: Enter pauses and resumes, s + Enter steps, q + Enter quits");
+
+        // Reading stdin blocks, so it gets its own thread rather than holding up the runtime.
+        let (sender, commands) = mpsc::unbounded_channel();
+        thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let command = match line {
+                    Ok(line) => match line.trim() {
+                        "" | "p" => Command::TogglePause,
+                        "s" => Command::Step,
+                        "q" => Command::Quit,
+                        other => {
+                            eprintln!("Unknown replay command '{}'", other);
+                            continue;
+                        }
+                    },
+                    Err(_) => break,
+                };
+
+                if sender.send(command).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            commands,
+            paused: false,
+        }
+    }
+
+    /// Applies the commands given since the last datagram, and waits for a command to go on with if
+    /// the replay is paused.
+    async fn next(&mut self) -> Flow {
+        while let Ok(command) = self.commands.try_recv() {
+            match command {
+                Command::TogglePause => self.paused = !self.paused,
+                // Stepping while playing pauses right here.
+                Command::Step => self.paused = true,
+                Command::Quit => return Flow::Quit,
+            }
+        }
+
+        if !self.paused {
+            return Flow::Play;
+        }
+
+        match self.commands.recv().await {
+            Some(Command::TogglePause) => self.paused = false,
+            Some(Command::Step) => {}
+            Some(Command::Quit) => return Flow::Quit,
+            // Nobody is left to resume the replay.
+            None => self.paused = false,
+        }
+        Flow::Resume
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(seconds: f64) -> Result<Position, String> {
+        Ok(Position::Time(Duration::from_secs_f64(seconds)))
+    }
+
+    #[test]
+    fn positions_parse() {
+        assert_eq!("90.5".parse(), time(90.5));
+        assert_eq!("0".parse(), time(0.0));
+        assert_eq!("1:30.5".parse(), time(90.5));
+        assert_eq!("0:59.9".parse(), time(59.9));
+        assert_eq!("2:00".parse(), time(120.0));
+        assert_eq!("lap1".parse(), Ok(Position::Lap(1)));
+        assert_eq!("lap12".parse(), Ok(Position::Lap(12)));
+    }
+
+    #[test]
+    fn invalid_positions_are_rejected() {
+        for s in &[
+            "", "lap", "lap0", "lap-1", "lapx", "lap1.5", "-1", "-0", "-0.5", "1:75", "1:60",
+            "1:-5", "-1:30", ":30", "1:", "1:30:00", "1.5:30", "inf", "NaN", "1e30", "abc",
+        ] {
+            assert!(s.parse::<Position>().is_err(), "'{}' was accepted", s);
+        }
+    }
+}
@@ -1,6 +1,9 @@
-use std::time::{Duration, Instant};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use chroma::KeyboardCustomKeyEffectBuilder;
+use chroma::{KeyboardCustomKeyEffectBuilder, LightingBackend};
 
 pub struct Tick {
     pub now: Instant,
@@ -27,8 +30,8 @@ impl ChromaState {
         self.keyboard.set_key(key, color);
     }
 
-    pub fn apply(self) -> chroma::Result<()> {
-        self.keyboard.build()?.set()?;
+    pub fn apply(self, backend: &Arc<dyn LightingBackend>) -> chroma::Result<()> {
+        self.keyboard.build(backend.clone())?.set()?;
         Ok(())
     }
 }
@@ -1,47 +1,350 @@
 use tokio::prelude::*;
 
-use std::{
-    mem::{size_of_val, MaybeUninit},
-    pin::Pin,
-    slice,
+use std::{io::SeekFrom, pin::Pin};
+
+use tokio::{fs::File, time::Duration};
+
+use forza::{
+    Datagram, DecodeError, Game, Horizon4Datagram, Horizon5Datagram, Motorsport2023Datagram,
+    Motorsport7Datagram, RecordingReader, Telemetry, RECORDING_MAGIC,
 };
+use serde::de::DeserializeOwned;
+
+use crate::replay::{self, ReplayOptions};
+
+/// Where telemetry is received unless told otherwise.
+pub const DEFAULT_LOCAL_ADDR: &str = "0.0.0.0:18733";
 
-use tokio::time::{delay_until, Duration, Instant};
+pub type TelemetryStream<'a> =
+    Pin<Box<dyn 'a + tokio::stream::Stream<Item = forza::Result<Telemetry>>>>;
 
-use forza::Horizon4Datagram;
+/// Telemetry read from a recording as fast as possible, along with the time it was recorded at
+/// since the first datagram.
+pub type RecordedStream<'a> =
+    Pin<Box<dyn 'a + tokio::stream::Stream<Item = forza::Result<(Duration, Telemetry)>>>>;
 
-pub async fn horizon4<'a>(
+/// How a recording is stored.
+#[derive(Copy, Clone, Debug)]
+pub enum Format<'a> {
+    /// A recording container, or a legacy raw recording of back-to-back datagrams.
+    Raw,
+    /// JSON datagrams, each followed by the separator.
+    Json { separator: &'a str },
+}
+
+/// Streams telemetry from a recording or from the network. Without a `game`, live datagrams are
+/// told apart by their size and JSON or legacy raw recordings are assumed to be from Forza Horizon
+/// 4.
+///
+/// Recordings are replayed according to `options`. Unless `realtime` is set they are replayed as
+/// fast as possible, in which case the consumer has to go by the recorded timestamps.
+pub async fn telemetry<'a>(
+    game: Option<Game>,
     local_addr: Option<&'a str>,
     recording: Option<&'a str>,
-) -> Result<
-    Pin<Box<dyn 'a + tokio::stream::Stream<Item = std::io::Result<Horizon4Datagram>>>>,
-    io::Error,
-> {
+    format: Format<'a>,
+    options: ReplayOptions,
+    realtime: bool,
+) -> Result<TelemetryStream<'a>, io::Error> {
     Ok(if let Some(recording) = recording {
-        let recording = tokio::fs::File::open(recording).await?;
-        let mut recording = Box::pin(io::BufReader::new(recording));
-        Box::pin(async_stream::try_stream! {
-            let start_time = Instant::now();
-            let mut start = None;
-            loop {
-                    let mut datagram = MaybeUninit::<Horizon4Datagram>::zeroed();
-                    let slice = unsafe { slice::from_raw_parts_mut(&mut datagram as *mut _ as *mut _, size_of_val(&datagram))};
-                    let read = recording.read_exact(slice).await?;
-                    if read < size_of_val(&datagram) {
-                        break;
-                    }
-                    let datagram = unsafe { datagram.assume_init() };
-                    if let Some((start_time, first_recorded_time)) = start.as_ref() {
-                        let since_first_time = Duration::from_millis(datagram.sled.timestamp_ms.wrapping_sub(*first_recorded_time) as u64);
-                        delay_until(*start_time + since_first_time).await;
-                    } else {
-                        start = Some((Instant::now(), datagram.sled.timestamp_ms));
-                    }
-                    yield datagram;
-            }
-        })
+        let first = open_recording(game, recording, format).await?;
+        replay::play(
+            first,
+            move || open_recording(game, recording, format),
+            options,
+            realtime,
+        )
     } else {
-        let local_addr = local_addr.unwrap_or("0.0.0.0:18733");
-        Box::pin(forza::horizon4(local_addr).await?)
+        let local_addr = local_addr.unwrap_or(DEFAULT_LOCAL_ADDR);
+        match game {
+            Some(game) => forza::telemetry(game, local_addr).await?,
+            None => Box::pin(forza::listen(local_addr).await?),
+        }
     })
 }
+
+async fn open_recording<'a>(
+    game: Option<Game>,
+    recording: &'a str,
+    format: Format<'a>,
+) -> Result<RecordedStream<'a>, io::Error> {
+    let recording = File::open(recording).await?;
+    Ok(match format {
+        Format::Raw => open_raw(game, recording).await?,
+        Format::Json { separator } => {
+            if separator.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the separator can't be empty",
+                ));
+            }
+
+            let recording = io::BufReader::new(recording);
+            match game.unwrap_or(Game::Horizon4) {
+                Game::Horizon4 => read_json::<_, Horizon4Datagram>(recording, separator),
+                Game::Horizon5 => read_json::<_, Horizon5Datagram>(recording, separator),
+                Game::Motorsport7 => read_json::<_, Motorsport7Datagram>(recording, separator),
+                Game::Motorsport2023 => {
+                    read_json::<_, Motorsport2023Datagram>(recording, separator)
+                }
+            }
+        }
+    })
+}
+
+/// Opens either a recording container or a legacy raw recording, telling them apart by the magic.
+async fn open_raw<'a>(
+    game: Option<Game>,
+    mut recording: File,
+) -> Result<RecordedStream<'a>, io::Error> {
+    let mut magic = [0u8; 8];
+    let len = read_full(&mut recording, &mut magic).await?;
+    recording.seek(SeekFrom::Start(0)).await?;
+    let recording = io::BufReader::new(recording);
+
+    if len == magic.len() && &magic == RECORDING_MAGIC {
+        return read_container(game, RecordingReader::open(recording).await?);
+    }
+
+    Ok(match game.unwrap_or(Game::Horizon4) {
+        Game::Horizon4 => read_raw::<_, Horizon4Datagram>(recording),
+        Game::Horizon5 => read_raw::<_, Horizon5Datagram>(recording),
+        Game::Motorsport2023 => read_raw::<_, Motorsport2023Datagram>(recording),
+        Game::Motorsport7 => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Forza Motorsport 7 sends datagrams of two different sizes, which legacy raw \
+                 recordings can't tell apart",
+            ))
+        }
+    })
+}
+
+/// Reads a recording container, timed by when each packet was received.
+fn read_container<'a, R: 'a + AsyncRead + Unpin>(
+    game: Option<Game>,
+    mut recording: RecordingReader<R>,
+) -> Result<RecordedStream<'a>, io::Error> {
+    let recorded_game = recording.header().game;
+    if let Some(game) = game.filter(|&game| game != recorded_game) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("the recording is from {}, not {}", recorded_game, game),
+        ));
+    }
+
+    Ok(Box::pin(async_stream::stream! {
+        let mut first = None;
+        loop {
+            let packet = match recording.next_packet().await {
+                Ok(Some(packet)) => packet,
+                // The recording ended.
+                Ok(None) => break,
+                Err(e) => {
+                    yield Err(e.into());
+                    break;
+                }
+            };
+            let first = *first.get_or_insert(packet.received);
+            let at = packet.received.checked_sub(first).unwrap_or_default();
+            yield Telemetry::decode_game(recorded_game, &packet.datagram)
+                .map(|telemetry| (at, telemetry))
+                .map_err(Into::into);
+        }
+    }))
+}
+
+/// Reads a legacy raw recording, i.e. back-to-back datagrams of type `D`. A truncated datagram at
+/// the end of the recording is yielded as an `Error::Decode` error.
+fn read_raw<'a, R: 'a + AsyncRead + Unpin, D: 'a + Datagram + Into<Telemetry>>(
+    recording: R,
+) -> RecordedStream<'a> {
+    let mut recording = Box::pin(recording);
+    Box::pin(async_stream::try_stream! {
+        let mut clock = TimestampClock::default();
+        loop {
+            let mut buf = vec![0u8; D::SIZE];
+            match read_full(&mut recording, &mut buf).await? {
+                // The recording ended.
+                0 => break,
+                len if len < buf.len() => Err(DecodeError::WrongSize(D::SIZE, len))?,
+                _ => {}
+            }
+            let telemetry: Telemetry = D::from_bytes(&buf)?.into();
+            yield (clock.at(&telemetry), telemetry);
+        }
+    })
+}
+
+/// Reads a JSON recording, i.e. datagrams of type `D` that are each followed by `separator`.
+fn read_json<'a, R: 'a + AsyncBufRead + Unpin, D: 'a + DeserializeOwned + Into<Telemetry>>(
+    recording: R,
+    separator: &'a str,
+) -> RecordedStream<'a> {
+    let mut recording = Box::pin(recording);
+    Box::pin(async_stream::try_stream! {
+        let mut clock = TimestampClock::default();
+        let mut record = Vec::new();
+        loop {
+            record.clear();
+            let ended = !read_record(&mut recording, separator.as_bytes(), &mut record).await?;
+
+            // Allow for blank lines, e.g. a trailing newline after the last separator.
+            if !record.iter().all(u8::is_ascii_whitespace) {
+                let datagram: D = serde_json::from_slice(&record).map_err(io::Error::from)?;
+                let telemetry: Telemetry = datagram.into();
+                yield (clock.at(&telemetry), telemetry);
+            }
+
+            if ended {
+                break;
+            }
+        }
+    })
+}
+
+/// Times datagrams by their `sled.timestamp_ms`, relative to the first datagram.
+#[derive(Default)]
+struct TimestampClock {
+    first: Option<u32>,
+}
+
+impl TimestampClock {
+    fn at(&mut self, telemetry: &Telemetry) -> Duration {
+        let first = *self.first.get_or_insert(telemetry.sled.timestamp_ms);
+        Duration::from_millis(telemetry.sled.timestamp_ms.wrapping_sub(first) as u64)
+    }
+}
+
+/// Reads the next record into `buf`, without its separator. Returns whether a separator was found,
+/// i.e. `false` once the reader has ended.
+async fn read_record(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    separator: &[u8],
+    buf: &mut Vec<u8>,
+) -> io::Result<bool> {
+    let last = *separator.last().unwrap();
+    loop {
+        if reader.read_until(last, buf).await? == 0 {
+            return Ok(false);
+        }
+        if buf.ends_with(separator) {
+            buf.truncate(buf.len() - separator.len());
+            return Ok(true);
+        }
+    }
+}
+
+/// Reads until `buf` is full or the reader ends, returning the number of bytes read.
+async fn read_full(reader: &mut (impl AsyncRead + Unpin), buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]).await? {
+            0 => break,
+            amt => len += amt,
+        }
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{io::BufReader, stream::StreamExt, time::Duration};
+
+    use forza::{Motorsport7Datagram, Motorsport7SledDatagram, Sled};
+
+    use super::{read_json, read_record};
+
+    /// A JSON datagram recorded at `timestamp_ms`.
+    fn datagram(timestamp_ms: u32) -> String {
+        serde_json::to_string(&Motorsport7Datagram::Sled(Motorsport7SledDatagram {
+            sled: Sled {
+                timestamp_ms,
+                ..Sled::default()
+            },
+        }))
+        .unwrap()
+    }
+
+    /// When each datagram in `recording` was recorded, or the error it ended with.
+    async fn read(recording: &str, separator: &str) -> Vec<Result<Duration, String>> {
+        // A small buffer splits records, and separators, across reads.
+        let recording = BufReader::with_capacity(4, recording.as_bytes());
+        read_json::<_, Motorsport7Datagram>(recording, separator)
+            .map(|datagram| datagram.map(|(at, _)| at).map_err(|e| e.to_string()))
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn records_are_split_at_the_separator() {
+        let separator = "<|>";
+        let mut recording = BufReader::with_capacity(4, "a<b|c<|>d<|><|>e".as_bytes());
+        let mut records = vec![];
+        loop {
+            let mut record = vec![];
+            let found = read_record(&mut recording, separator.as_bytes(), &mut record)
+                .await
+                .unwrap();
+            records.push((String::from_utf8(record).unwrap(), found));
+            if !found {
+                break;
+            }
+        }
+        assert_eq!(
+            records,
+            [
+                ("a<b|c".to_owned(), true),
+                ("d".to_owned(), true),
+                (String::new(), true),
+                ("e".to_owned(), false),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn datagrams_are_timed_from_the_first() {
+        let recording = format!("{}\n{}\n{}", datagram(500), datagram(516), datagram(550));
+        assert_eq!(
+            read(&recording, "\n").await,
+            [
+                Ok(Duration::from_millis(0)),
+                Ok(Duration::from_millis(16)),
+                Ok(Duration::from_millis(50)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn custom_separators_can_be_used() {
+        let recording = format!("{}--{}--", datagram(0), datagram(16));
+        assert_eq!(
+            read(&recording, "--").await,
+            [Ok(Duration::from_millis(0)), Ok(Duration::from_millis(16))]
+        );
+    }
+
+    #[tokio::test]
+    async fn blank_records_are_skipped() {
+        let recording = format!("\n{}\n\n  \n{}\n\n", datagram(0), datagram(16));
+        assert_eq!(
+            read(&recording, "\n").await,
+            [Ok(Duration::from_millis(0)), Ok(Duration::from_millis(16))]
+        );
+
+        let recording = format!("{};\n{};\n", datagram(0), datagram(16));
+        assert_eq!(
+            read(&recording, ";").await,
+            [Ok(Duration::from_millis(0)), Ok(Duration::from_millis(16))]
+        );
+    }
+
+    #[tokio::test]
+    async fn malformed_records_end_the_recording_with_an_error() {
+        let recording = format!("{}\n{{\"Sled\": 1}}\n{}\n", datagram(0), datagram(16));
+        let read = read(&recording, "\n").await;
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0], Ok(Duration::from_millis(0)));
+        assert!(read[1].is_err());
+    }
+}
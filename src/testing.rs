@@ -0,0 +1,88 @@
+//! Helpers for the tests, which run effects against a `VirtualKeyboard`.
+
+use std::{path::PathBuf, rc::Rc, sync::Arc, time::Instant};
+
+use chroma::{Frame, LightingBackend, VirtualKeyboard};
+use forza::{Dash, Game, Sled, Telemetry};
+use rgb::RGB8;
+
+use crate::{
+    config::{Config, Problem},
+    driver::{Driver, Session},
+    learned::LearnedMaxima,
+};
+
+pub const WHITE: RGB8 = RGB8::new(0xff, 0xff, 0xff);
+pub const BLACK: RGB8 = RGB8::new(0, 0, 0);
+pub const RED: RGB8 = RGB8::new(0xff, 0, 0);
+pub const BLUE: RGB8 = RGB8::new(0, 0, 0xff);
+
+/// Nothing is learned by the tests, so the file is never written.
+pub fn learned() -> Rc<LearnedMaxima> {
+    let path = std::env::temp_dir().join("forza-chroma-test-learned.toml");
+    Rc::new(LearnedMaxima::load(path).unwrap())
+}
+
+/// A path in the temporary directory that no other test uses.
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("forza-chroma-{}-{}", std::process::id(), name))
+}
+
+/// Builds the effects in `config`, which defines the colors `white`, `red` and `blue` for them.
+pub fn driver(config: &str) -> Driver {
+    Driver::from_config(&with_colors(config), &learned())
+        .unwrap_or_else(|errors| panic!("invalid config: {:?}", errors))
+}
+
+/// The problems with the effects in `config`, which has the same colors as in `driver`.
+pub fn problems(config: &str) -> Vec<Problem> {
+    match Driver::from_config(&with_colors(config), &learned()) {
+        Ok(_) => panic!("expected problems with {}", config),
+        Err(errors) => errors.into_iter().map(|error| error.problem).collect(),
+    }
+}
+
+fn with_colors(config: &str) -> Config {
+    let config = format!(
+        "[colors]\nwhite = \"ffffff\"\nred = \"ff0000\"\nblue = \"0000ff\"\n\n{}",
+        config
+    );
+    toml::from_str(&config).unwrap()
+}
+
+/// Telemetry from a race that's on, with an engine that idles at 1000 rpm and revs to 8000.
+pub fn telemetry(rpm: f32) -> Telemetry {
+    Telemetry {
+        game: Game::Horizon4,
+        sled: Sled {
+            is_race_on: 1,
+            engine_max_rpm: 8000.0,
+            engine_idle_rpm: 1000.0,
+            current_engine_rpm: rpm,
+            ..Sled::default()
+        },
+        dash: Some(Dash::default()),
+        tire_wear: None,
+        track_ordinal: None,
+    }
+}
+
+/// Shows one datagram with the effects in `config`, returning what the keyboard shows.
+pub fn render(config: &str, telemetry: &Telemetry) -> Frame {
+    show(&mut driver(config).start(), telemetry, Instant::now())
+}
+
+/// Feeds one datagram to a running session, returning what the keyboard shows at `now`.
+pub fn show(session: &mut Session<'_>, telemetry: &Telemetry, now: Instant) -> Frame {
+    let keyboard = Arc::new(VirtualKeyboard::new());
+    let backend: Arc<dyn LightingBackend> = keyboard.clone();
+
+    session.step(telemetry, now).apply(&backend).unwrap();
+
+    keyboard.current().unwrap()
+}
+
+/// The colors along a row, from `columns.start` on.
+pub fn row(frame: &Frame, row: u8, columns: std::ops::Range<u8>) -> Vec<RGB8> {
+    columns.map(|column| frame.position(row, column)).collect()
+}
@@ -0,0 +1,115 @@
+use std::{fs, time::SystemTime};
+
+use tokio::time::{interval, Duration, Interval};
+
+/// How often the config's modification time is checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Watches the config file for changes by polling its modification time.
+pub struct ConfigWatcher {
+    path: String,
+    interval: Interval,
+    modified: Option<SystemTime>,
+    /// A change that hasn't been seen on two polls in a row yet. Editors often write a file in
+    /// several steps, and the config shouldn't be reloaded halfway through.
+    settling: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_owned(),
+            interval: interval(POLL_INTERVAL),
+            modified: modified(path),
+            settling: None,
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Waits until the config has changed and stopped changing. Dropping the future before it
+    /// completes doesn't lose the change.
+    pub async fn changed(&mut self) {
+        loop {
+            self.interval.tick().await;
+
+            // The file might be missing for a moment while an editor replaces it.
+            let modified = match modified(&self.path) {
+                Some(modified) => modified,
+                None => continue,
+            };
+            if Some(modified) == self.modified {
+                self.settling = None;
+            } else if Some(modified) == self.settling {
+                self.modified = Some(modified);
+                self.settling = None;
+                return;
+            } else {
+                self.settling = Some(modified);
+            }
+        }
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, time::UNIX_EPOCH};
+
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::testing;
+
+    fn touch(path: &std::path::Path, seconds: u64) {
+        let file = File::options().write(true).open(path).unwrap();
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn each_change_is_seen_once() {
+        let path = testing::temp_path("watched.toml");
+        fs::write(&path, "").unwrap();
+        touch(&path, 1);
+        let mut watcher = ConfigWatcher::new(path.to_str().unwrap());
+        // Long enough for a few polls.
+        let wait = POLL_INTERVAL * 4;
+
+        assert!(timeout(wait, watcher.changed()).await.is_err());
+
+        touch(&path, 2);
+        assert!(timeout(wait, watcher.changed()).await.is_ok());
+        assert!(timeout(wait, watcher.changed()).await.is_err());
+
+        touch(&path, 3);
+        assert!(timeout(wait, watcher.changed()).await.is_ok());
+        assert!(timeout(wait, watcher.changed()).await.is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn missing_files_arent_a_change() {
+        let path = testing::temp_path("replaced.toml");
+        fs::write(&path, "").unwrap();
+        touch(&path, 1);
+        let mut watcher = ConfigWatcher::new(path.to_str().unwrap());
+        let wait = POLL_INTERVAL * 4;
+
+        fs::remove_file(&path).unwrap();
+        assert!(timeout(wait, watcher.changed()).await.is_err());
+
+        // Until it's back with a new modification time.
+        fs::write(&path, "").unwrap();
+        touch(&path, 2);
+        assert!(timeout(wait, watcher.changed()).await.is_ok());
+
+        fs::remove_file(path).unwrap();
+    }
+}